@@ -0,0 +1,462 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use hashbrown::HashMap;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt as _, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice as from_json_slice, json};
+use tracing::{Level, event};
+
+use crate::docker_healer::DockerHealer;
+use crate::metrics::Metrics;
+use crate::reliability::ReliabilityStats;
+use crate::state::{EventKind, Pause, TrackedContainer};
+use crate::utils::serializers;
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const MAX_BODY_SIZE: usize = 4 * 1024;
+
+/// Routes of the HTTP listener.
+#[derive(Clone)]
+pub struct Api {
+    healer: Arc<DockerHealer>,
+    metrics: Arc<Metrics>,
+    token: Option<Arc<str>>,
+}
+
+#[derive(Serialize)]
+struct ContainerStatus<'a> {
+    #[serde(flatten)]
+    container: &'a TrackedContainer,
+    #[serde(serialize_with = "serializers::optional_timestamp")]
+    next_eligible_restart_at: Option<SystemTime>,
+}
+
+#[derive(Serialize)]
+struct ContainerReliability<'a> {
+    container_name: &'a str,
+    compose_service: Option<&'a str>,
+    #[serde(flatten)]
+    stats: ReliabilityStats,
+}
+
+#[derive(Serialize)]
+struct ReliabilityReport<'a> {
+    containers: Vec<ContainerReliability<'a>>,
+    /// Keyed by compose service.
+    services: HashMap<&'a str, ReliabilityStats>,
+}
+
+/// Routes that change what the healer does, these require the token.
+enum Control<'a> {
+    Pause(Option<&'a str>),
+    Resume(Option<&'a str>),
+    Heal(&'a str),
+    Exclude(&'a str),
+    Include(&'a str),
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PauseRequest {
+    /// e.g. `30m`, pauses until resumed when absent.
+    duration: Option<Box<str>>,
+}
+
+/// Compares without bailing out on the first difference, so the time taken doesn't tell how much of the token was right.
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |difference, (left, right)| difference | (left ^ right))
+            == 0
+}
+
+fn text_response(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+
+    response
+}
+
+fn json_response<T: Serialize + ?Sized>(value: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(value) {
+        Ok(serialized) => {
+            let mut response = Response::new(Full::new(Bytes::from(serialized)));
+            response.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+
+            response
+        },
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Failed to serialize response");
+
+            text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to serialize response",
+            )
+        },
+    }
+}
+
+impl Api {
+    pub fn new(healer: Arc<DockerHealer>, metrics: Arc<Metrics>, token: Option<Box<str>>) -> Self {
+        Self {
+            healer,
+            metrics,
+            token: token.map(Arc::from),
+        }
+    }
+
+    pub async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        let control = match (&method, &*segments) {
+            (&Method::GET, &["healthz"]) => return self.healthz(),
+            (&Method::GET, &["metrics"]) => return self.metrics(),
+            (&Method::GET, &["containers"]) => return self.containers(),
+            (&Method::GET, &["events"]) => return self.events(),
+            (&Method::GET, &["config"]) => {
+                return json_response(&self.healer.settings().effective_config);
+            },
+            (&Method::GET, &["overrides"]) => return self.overrides(),
+            (&Method::GET, &["stats"]) => return self.stats(),
+            (&Method::POST, &["pause"]) => Control::Pause(None),
+            (&Method::POST, &["pause", container_name]) => Control::Pause(Some(container_name)),
+            (&Method::POST, &["resume"]) => Control::Resume(None),
+            (&Method::POST, &["resume", container_name]) => Control::Resume(Some(container_name)),
+            (&Method::POST, &["heal", container_name]) => Control::Heal(container_name),
+            (&Method::PUT, &["exclusions", container_name]) => Control::Exclude(container_name),
+            (&Method::DELETE, &["exclusions", container_name]) => Control::Include(container_name),
+            _ => return text_response(StatusCode::NOT_FOUND, "Not found"),
+        };
+
+        if let Err(response) = self.authorize(request.headers()) {
+            return response;
+        }
+
+        match control {
+            Control::Pause(container_name) => {
+                let pause_request = match read_json::<PauseRequest>(request).await {
+                    Ok(pause_request) => pause_request,
+                    Err(response) => return response,
+                };
+
+                self.pause(container_name, pause_request.duration.as_deref())
+            },
+            Control::Resume(container_name) => self.resume(container_name),
+            Control::Heal(container_name) => self.heal(container_name).await,
+            Control::Exclude(container_name) => self.exclude(container_name),
+            Control::Include(container_name) => self.include(container_name),
+        }
+    }
+
+    #[expect(clippy::result_large_err, reason = "Only used to bail out early")]
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), Response<Full<Bytes>>> {
+        let Some(ref token) = self.token else {
+            return Err(text_response(
+                StatusCode::FORBIDDEN,
+                "Control routes are disabled, set `AUTOHEAL_API_TOKEN` to enable them",
+            ));
+        };
+
+        let provided = headers
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match provided {
+            Some(provided) if tokens_match(token, provided) => Ok(()),
+            _ => {
+                event!(
+                    Level::WARN,
+                    "Rejected control request with a missing or wrong token"
+                );
+
+                Err(text_response(StatusCode::UNAUTHORIZED, "Unauthorized"))
+            },
+        }
+    }
+
+    fn containers(&self) -> Response<Full<Bytes>> {
+        let state = self.healer.state();
+
+        let mut containers = state
+            .containers
+            .values()
+            .map(|container| ContainerStatus {
+                container,
+                next_eligible_restart_at: state.next_eligible_restart_at(container),
+            })
+            .collect::<Vec<_>>();
+
+        containers.sort_unstable_by_key(|status| status.container.unhealthy_since);
+
+        json_response(&containers)
+    }
+
+    fn overrides(&self) -> Response<Full<Bytes>> {
+        json_response(&self.healer.state().overrides)
+    }
+
+    fn pause(&self, container_name: Option<&str>, duration: Option<&str>) -> Response<Full<Bytes>> {
+        let until = match duration.map(|duration| pause_until(duration, SystemTime::now())) {
+            Some(Ok(until)) => Some(until),
+            Some(Err(message)) => return text_response(StatusCode::BAD_REQUEST, message),
+            None => None,
+        };
+
+        let pause = Pause { until };
+
+        {
+            let mut state = self.healer.state();
+
+            match container_name {
+                Some(container_name) => {
+                    state
+                        .overrides
+                        .paused_containers
+                        .insert(container_name.into(), pause);
+                },
+                None => state.overrides.paused = Some(pause),
+            }
+        }
+
+        event!(
+            Level::INFO,
+            container_name = container_name.unwrap_or("<ALL CONTAINERS>"),
+            duration = duration.unwrap_or("until resumed"),
+            "Paused healing"
+        );
+
+        self.overrides()
+    }
+
+    fn resume(&self, container_name: Option<&str>) -> Response<Full<Bytes>> {
+        let resumed = {
+            let mut state = self.healer.state();
+
+            match container_name {
+                Some(container_name) => state
+                    .overrides
+                    .paused_containers
+                    .remove(container_name)
+                    .is_some(),
+                None => state.overrides.paused.take().is_some(),
+            }
+        };
+
+        if !resumed {
+            return text_response(StatusCode::NOT_FOUND, "Not paused");
+        }
+
+        event!(
+            Level::INFO,
+            container_name = container_name.unwrap_or("<ALL CONTAINERS>"),
+            "Resumed healing"
+        );
+
+        self.overrides()
+    }
+
+    async fn heal(&self, container_name: &str) -> Response<Full<Bytes>> {
+        match self
+            .healer
+            .heal(container_name, "Requested through the API")
+            .await
+        {
+            Ok(Some(kind @ EventKind::Restarted)) => json_response(&json!({ "result": kind })),
+            Ok(Some(kind)) => {
+                let mut response = json_response(&json!({ "result": kind }));
+                *response.status_mut() = StatusCode::BAD_GATEWAY;
+
+                response
+            },
+            Ok(None) => text_response(
+                StatusCode::NOT_FOUND,
+                "No running container with that name or id",
+            ),
+            Err(error) => {
+                event!(Level::ERROR, ?error, %container_name, "Failed to find container to heal");
+
+                self.metrics.docker_api_error("list_containers");
+
+                text_response(StatusCode::BAD_GATEWAY, "Failed to list containers")
+            },
+        }
+    }
+
+    fn exclude(&self, container_name: &str) -> Response<Full<Bytes>> {
+        self.healer
+            .state()
+            .overrides
+            .excluded_containers
+            .insert(container_name.into());
+
+        event!(Level::INFO, %container_name, "Excluded container");
+
+        self.overrides()
+    }
+
+    fn include(&self, container_name: &str) -> Response<Full<Bytes>> {
+        let removed = self
+            .healer
+            .state()
+            .overrides
+            .excluded_containers
+            .remove(container_name);
+
+        if !removed {
+            return text_response(StatusCode::NOT_FOUND, "Not excluded at runtime");
+        }
+
+        event!(Level::INFO, %container_name, "Removed runtime exclusion");
+
+        self.overrides()
+    }
+
+    fn events(&self) -> Response<Full<Bytes>> {
+        let state = self.healer.state();
+
+        // newest first
+        let events = state.events.iter().rev().collect::<Vec<_>>();
+
+        json_response(&events)
+    }
+
+    fn stats(&self) -> Response<Full<Bytes>> {
+        let now = SystemTime::now();
+        let state = self.healer.state();
+
+        let mut containers = state
+            .reliability
+            .containers(now)
+            .map(
+                |(container_name, compose_service, stats)| ContainerReliability {
+                    container_name,
+                    compose_service,
+                    stats,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        containers.sort_unstable_by_key(|container| container.container_name);
+
+        json_response(&ReliabilityReport {
+            containers,
+            services: state.reliability.services(now),
+        })
+    }
+
+    fn healthz(&self) -> Response<Full<Bytes>> {
+        match self.healer.liveness() {
+            Ok(()) => text_response(StatusCode::OK, "OK"),
+            Err(reason) => text_response(StatusCode::SERVICE_UNAVAILABLE, reason),
+        }
+    }
+
+    fn metrics(&self) -> Response<Full<Bytes>> {
+        match self.metrics.encode() {
+            Ok(encoded) => {
+                let mut response = Response::new(Full::new(Bytes::from(encoded)));
+                response.headers_mut().insert(
+                    hyper::header::CONTENT_TYPE,
+                    HeaderValue::from_static(OPENMETRICS_CONTENT_TYPE),
+                );
+
+                response
+            },
+            Err(error) => {
+                event!(Level::ERROR, ?error, "Failed to encode metrics");
+
+                text_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to encode metrics",
+                )
+            },
+        }
+    }
+}
+
+/// Reads a JSON body, an empty body is the same as `{}`.
+#[expect(clippy::result_large_err, reason = "Only used to bail out early")]
+async fn read_json<T: Default + for<'de> Deserialize<'de>>(
+    request: Request<Incoming>,
+) -> Result<T, Response<Full<Bytes>>> {
+    let body = match Limited::new(request.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+    {
+        Ok(collected) => collected.to_bytes(),
+        Err(error) => {
+            event!(Level::DEBUG, ?error, "Failed to read request body");
+
+            return Err(text_response(
+                StatusCode::BAD_REQUEST,
+                "Failed to read body",
+            ));
+        },
+    };
+
+    if body.is_empty() {
+        return Ok(T::default());
+    }
+
+    from_json_slice(&body).map_err(|error| {
+        event!(Level::DEBUG, ?error, "Failed to parse request body");
+
+        text_response(StatusCode::BAD_REQUEST, "Invalid JSON body")
+    })
+}
+
+/// `humantime` goes up to `u64::MAX` seconds, which is well past what a [`SystemTime`] can hold.
+fn pause_until(duration: &str, now: SystemTime) -> Result<SystemTime, &'static str> {
+    let duration = humantime::parse_duration(duration)
+        .map_err(|_| "Invalid duration, use something like `30m` or `1h 30m`")?;
+
+    now.checked_add(duration).ok_or("Duration is too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::api::{pause_until, tokens_match};
+
+    #[test]
+    fn pause_until_rejects_overflow() {
+        let now = SystemTime::now();
+
+        assert_eq!(pause_until("30m", now), Ok(now + Duration::from_mins(30)));
+        assert_eq!(
+            pause_until("500000000000y", now),
+            Err("Duration is too long")
+        );
+        assert!(pause_until("soon", now).is_err(), "Not a duration");
+    }
+
+    #[test]
+    fn tokens_match_equal() {
+        assert!(tokens_match("s3cr3t", "s3cr3t"), "Same tokens should match");
+    }
+
+    #[test]
+    fn tokens_match_different() {
+        assert!(
+            !tokens_match("s3cr3t", "s3cr3x"),
+            "Different tokens shouldn't match"
+        );
+        assert!(!tokens_match("s3cr3t", "s3cr3"), "Prefixes shouldn't match");
+        assert!(!tokens_match("s3cr3t", ""), "Empty tokens shouldn't match");
+    }
+}
@@ -0,0 +1,327 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+
+use color_eyre::eyre;
+use serde::Serialize;
+use serde_json::to_vec as to_json_vec;
+use tracing::{Level, event};
+
+use crate::config::AuditConfig;
+use crate::container_details::{ContainerDetails, HealthProbe};
+use crate::state::{EventKind, HealEvent};
+use crate::utils::serializers;
+
+const SHORT_ID_LENGTH: usize = 12;
+
+/// Records waiting for the writer, beyond this they're dropped rather than holding up the checks.
+const QUEUE_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Unhealthy,
+    Skipped,
+    Restarted,
+    RestartFailed,
+    Recovered,
+    Notified,
+    NotificationFailed,
+}
+
+impl From<EventKind> for AuditAction {
+    fn from(kind: EventKind) -> Self {
+        match kind {
+            EventKind::Restarted => AuditAction::Restarted,
+            EventKind::RestartFailed => AuditAction::RestartFailed,
+            EventKind::Skipped => AuditAction::Skipped,
+            EventKind::Recovered => AuditAction::Recovered,
+        }
+    }
+}
+
+/// One line in the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    #[serde(serialize_with = "serializers::timestamp")]
+    pub at: SystemTime,
+    pub action: AuditAction,
+    pub container_id: &'a str,
+    pub container_name: Option<&'a str>,
+    pub image: Option<&'a str>,
+    pub health_log: Option<&'a [HealthProbe]>,
+    pub reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<&'a str>,
+}
+
+impl<'a> AuditRecord<'a> {
+    /// `container_id` is shortened to what `docker ps` shows, as the notifier only knows that one.
+    pub fn new(
+        action: AuditAction,
+        container_id: &'a str,
+        container_name: Option<&'a str>,
+        details: Option<&'a ContainerDetails>,
+    ) -> Self {
+        Self {
+            at: SystemTime::now(),
+            action,
+            container_id: container_id.get(..SHORT_ID_LENGTH).unwrap_or(container_id),
+            container_name,
+            image: details.map(|details| &*details.image),
+            health_log: details.map(|details| &*details.health_log),
+            reason: None,
+            destination: None,
+        }
+    }
+
+    pub fn from_event(event: &'a HealEvent, details: Option<&'a ContainerDetails>) -> Self {
+        Self {
+            at: event.at,
+            reason: event.reason.as_deref(),
+            ..Self::new(
+                event.kind.into(),
+                &event.container_id,
+                event.container_name.as_deref(),
+                details,
+            )
+        }
+    }
+}
+
+/// Appends to a file, and moves it to `<path>.1` (and `.1` to `.2`, ...) once it would grow over `max_size`.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+
+    PathBuf::from(rotated)
+}
+
+fn open_append(path: &Path) -> Result<(File, u64), std::io::Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+
+    Ok((file, size))
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    match std::fs::rename(from, to) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> Result<Self, std::io::Error> {
+        let (file, size) = open_append(&path)?;
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> Result<(), std::io::Error> {
+        let length = u64::try_from(line.len()).unwrap_or(u64::MAX);
+
+        if self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line)?;
+        self.size += length;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), std::io::Error> {
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            // the oldest one gets overwritten
+            for index in (1..self.max_files).rev() {
+                rename_if_exists(
+                    &rotated_path(&self.path, index),
+                    &rotated_path(&self.path, index + 1),
+                )?;
+            }
+
+            std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        (self.file, self.size) = open_append(&self.path)?;
+
+        Ok(())
+    }
+}
+
+fn write_lines(mut file: RotatingFile, receiver: &Receiver<Vec<u8>>) {
+    for line in receiver {
+        if let Err(error) = file.write_line(&line) {
+            event!(
+                Level::ERROR,
+                ?error,
+                record = %String::from_utf8_lossy(&line).trim_end(),
+                "Failed to write to audit log"
+            );
+        }
+    }
+}
+
+/// JSONL record of every decision we made, for when things need to be reconstructed after an incident.
+///
+/// The file is written to from a thread of its own, as writing and rotating block.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    sender: Option<SyncSender<Vec<u8>>>,
+}
+
+/// The thread behind the [`AuditLog`]s, to wait for before exiting.
+pub struct AuditWriter {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AuditWriter {
+    /// Returns once everything queued is written, which needs every [`AuditLog`] to be dropped first.
+    pub async fn finish(self) {
+        let Some(thread) = self.thread else {
+            return;
+        };
+
+        let _r = tokio::task::spawn_blocking(move || thread.join()).await;
+    }
+}
+
+impl AuditLog {
+    /// Opens (or creates) the audit log, when configured.
+    ///
+    /// # Errors
+    ///
+    /// When the file cannot be opened for appending.
+    pub fn open(config: AuditConfig) -> Result<(Self, AuditWriter), eyre::Report> {
+        let Some(path) = config.path else {
+            return Ok((Self::default(), AuditWriter { thread: None }));
+        };
+
+        let file = RotatingFile::open(path.clone(), config.max_size, config.max_files).map_err(
+            |error| {
+                eyre::Report::new(error)
+                    .wrap_err(format!("Failed to open audit log `{}`", path.display()))
+            },
+        )?;
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+
+        let thread = thread::Builder::new()
+            .name("audit".to_owned())
+            .spawn(move || write_lines(file, &receiver))
+            .map_err(|error| eyre::Report::new(error).wrap_err("Failed to start audit writer"))?;
+
+        Ok((
+            Self {
+                sender: Some(sender),
+            },
+            AuditWriter {
+                thread: Some(thread),
+            },
+        ))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn write(&self, record: &AuditRecord<'_>) {
+        let Some(ref sender) = self.sender else {
+            return;
+        };
+
+        let result = to_json_vec(record)
+            .map_err(eyre::Report::new)
+            .and_then(|mut line| {
+                line.push(b'\n');
+
+                sender.try_send(line).map_err(eyre::Report::new)
+            });
+
+        if let Err(error) = result {
+            event!(
+                Level::ERROR,
+                ?error,
+                ?record,
+                "Failed to write to audit log"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::audit::{RotatingFile, rotated_path};
+    use crate::utils::test_directory::TestDirectory;
+
+    #[test]
+    fn rotates_when_full() {
+        let directory = TestDirectory::new("audit-rotates");
+        let path = directory.join("audit.jsonl");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "second\n"
+        );
+        assert!(
+            !rotated_path(&path, 3).exists(),
+            "Only 2 rotated files are kept"
+        );
+    }
+
+    #[test]
+    fn appends_to_existing() {
+        let directory = TestDirectory::new("audit-appends");
+        let path = directory.join("audit.jsonl");
+
+        std::fs::write(&path, "existing\n").unwrap();
+
+        let mut file = RotatingFile::open(path.clone(), 1024, 2).unwrap();
+        file.write_line(b"new\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "existing\nnew\n");
+    }
+
+    #[test]
+    fn rotated_path_appends_index() {
+        assert_eq!(
+            rotated_path(&PathBuf::from("/var/log/audit.jsonl"), 3),
+            PathBuf::from("/var/log/audit.jsonl.3")
+        );
+    }
+}
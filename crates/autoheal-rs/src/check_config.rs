@@ -0,0 +1,274 @@
+use std::process::ExitCode;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use http::{Method, Uri};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use tracing::{Level, event};
+use twistlock::client::Client;
+use twistlock::endpoint::{ApiEndpoint, ApiEndpointCallError};
+
+use crate::config::{AppConfig, HealerConfig};
+use crate::shutdown::Shutdown;
+use crate::{list, typos, unhealthy_filters, webhook};
+
+/// The health filter showed up in 1.24.
+const MINIMUM_API_VERSION: (u32, u32) = (1, 24);
+
+/// Restarting a container that doesn't exist tells us whether we're allowed to, without restarting anything.
+///
+/// `~` isn't allowed in container names, nor in ids, so this can't match, or be a prefix of, an actual container.
+const PROBE_CONTAINER: &str = "autoheal~check~config";
+
+/// `twistlock` doesn't have an endpoint for the version.
+struct Version;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerVersion {
+    version: Box<str>,
+    api_version: Box<str>,
+    os: Option<Box<str>>,
+    arch: Option<Box<str>>,
+}
+
+impl ApiEndpoint for Version {
+    type Request = ();
+    type Response = DockerVersion;
+    type Error = JsonValue;
+
+    const METHOD: Method = Method::GET;
+
+    fn path_and_query(&(): &Self::Request) -> Result<String, std::io::Error> {
+        Ok("/version".to_owned())
+    }
+}
+
+fn parse_api_version(api_version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = api_version.split_once('.')?;
+
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+async fn check_version(client: &Client) -> Result<(), eyre::Report> {
+    let version = client
+        .call::<Version>(&())
+        .await
+        .map_err(|error| eyre::Report::new(error).wrap_err("Failed to reach Docker"))?;
+
+    event!(
+        Level::INFO,
+        version = %version.version,
+        api_version = %version.api_version,
+        os = version.os.as_deref(),
+        arch = version.arch.as_deref(),
+        "Connected to Docker"
+    );
+
+    match parse_api_version(&version.api_version) {
+        Some(api_version) if api_version >= MINIMUM_API_VERSION => Ok(()),
+        Some(_) => Err(eyre::Report::msg(format!(
+            "API version {} is too old, at least {}.{} is needed",
+            version.api_version, MINIMUM_API_VERSION.0, MINIMUM_API_VERSION.1
+        ))),
+        None => Err(eyre::Report::msg(format!(
+            "Could not parse API version `{}`",
+            version.api_version
+        ))),
+    }
+}
+
+async fn check_restart_permission(client: &Client) -> Result<(), eyre::Report> {
+    match client
+        .restart_container(PROBE_CONTAINER, Duration::ZERO)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(ApiEndpointCallError::Typed(ref body))
+            if body
+                .get("message")
+                .and_then(JsonValue::as_str)
+                .is_some_and(|message| message.starts_with("No such container")) =>
+        {
+            Ok(())
+        },
+        Err(error) => {
+            Err(eyre::Report::new(error).wrap_err("Docker doesn't allow restarting containers"))
+        },
+    }
+}
+
+/// Lists the running containers the label filter lets through, and what would happen to them.
+async fn check_containers(
+    client: &Client,
+    healer_config: &HealerConfig,
+    container_label: Option<&str>,
+) -> Result<(), eyre::Report> {
+    let containers = list::managed_containers(client, healer_config, container_label).await?;
+
+    let filters = unhealthy_filters::build(container_label);
+
+    event!(
+        Level::INFO,
+        label = container_label.unwrap_or("all"),
+        matched = containers.len(),
+        "Containers matching the label filter"
+    );
+
+    for container in &containers {
+        event!(
+            Level::INFO,
+            container_name = container.name.as_deref(),
+            container_short_id = %container.short_id,
+            state = %container.state,
+            health = %container.health,
+            excluded = container.excluded,
+            action = %container.action,
+            "Matched container",
+        );
+
+        for (label, suggestion) in typos::unknown_labels(
+            container.labels.iter().map(|&(ref key, _)| &**key),
+            &healer_config.label_keys,
+            &filters,
+        ) {
+            event!(
+                Level::WARN,
+                container_name = container.name.as_deref(),
+                %label,
+                did_you_mean = suggestion,
+                "Unknown label"
+            );
+        }
+    }
+
+    // not a problem as such, it might not be running right now, but it could be a typo
+    for excluded in &healer_config.exclude_containers {
+        if !containers
+            .iter()
+            .any(|container| container.name.as_ref() == Some(excluded))
+        {
+            event!(
+                Level::WARN,
+                container_name = %excluded,
+                "Excluded container doesn't match any container"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_notification(webhook_url: Option<&Uri>) -> Result<(), eyre::Report> {
+    let Some(webhook_url) = webhook_url else {
+        return Err(eyre::Report::msg(
+            "There is no `WEBHOOK_URL` to send a test notification to",
+        ));
+    };
+
+    webhook::send_test_notification(webhook_url).await
+}
+
+/// Logs the outcome, and returns whether it was a problem.
+fn report(check: &'static str, result: Result<(), eyre::Report>) -> bool {
+    match result {
+        Ok(()) => {
+            event!(Level::INFO, check, "Check passed");
+
+            false
+        },
+        Err(error) => {
+            event!(Level::ERROR, check, ?error, "Check failed");
+
+            true
+        },
+    }
+}
+
+/// Checks the settings against the Docker endpoint, and the webhook when asked to, for use in CI.
+pub async fn run(config: AppConfig, test_notification: bool) -> Shutdown {
+    // we got here, so the settings parsed
+    event!(Level::INFO, config = %config.effective_config, "Effective configuration");
+
+    let client = match config.docker_config.client() {
+        Ok(client) => client,
+        Err(error) => {
+            report("Docker client", Err(error));
+
+            return Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Configuration check failed",
+            };
+        },
+    };
+
+    let mut problems = [
+        report("Docker API version", check_version(&client).await),
+        report(
+            "Restart permission",
+            check_restart_permission(&client).await,
+        ),
+        report(
+            "Containers",
+            check_containers(
+                &client,
+                &config.healer_config,
+                config.container_label.as_deref(),
+            )
+            .await,
+        ),
+    ]
+    .into_iter()
+    .filter(|&problem| problem)
+    .count();
+
+    if test_notification {
+        problems += usize::from(report(
+            "Test notification",
+            check_notification(config.notifier_config.webhook_url.as_ref()).await,
+        ));
+    }
+
+    if problems == 0 {
+        event!(Level::INFO, "Configuration is valid");
+
+        Shutdown::Success
+    } else {
+        event!(Level::ERROR, problems, "Configuration has problems");
+
+        Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "Configuration check failed",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::check_config::{PROBE_CONTAINER, parse_api_version};
+
+    #[test]
+    fn parses_api_version() {
+        assert_eq!(parse_api_version("1.47"), Some((1, 47)));
+        assert_eq!(parse_api_version("1.9"), Some((1, 9)));
+        assert!(
+            parse_api_version("1.9") < parse_api_version("1.24"),
+            "Compared as numbers"
+        );
+        assert_eq!(parse_api_version("latest"), None);
+    }
+
+    #[test]
+    fn probe_container_cannot_exist() {
+        // Docker only accepts `[a-zA-Z0-9][a-zA-Z0-9_.-]+` as a name, and ids are hex
+        assert!(
+            PROBE_CONTAINER
+                .chars()
+                .any(|c| !(c.is_ascii_alphanumeric() || "_.-".contains(c))),
+            "Not a valid name, nor an id"
+        );
+    }
+}
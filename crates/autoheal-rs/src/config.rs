@@ -1,18 +1,77 @@
+use std::ffi::{OsStr, OsString};
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr as _;
 use std::time::Duration;
 
-use clap::Parser;
+use clap::{ArgAction, CommandFactory as _, FromArgMatches as _, Parser, Subcommand};
 use color_eyre::eyre;
 use hyper::Uri;
+use serde::Serialize;
+use serde_json::{Value as JsonValue, to_value as to_json_value};
 use tracing::{Level, event};
+use twistlock::client::Client;
 use twistlock::config::Endpoint;
 
+use crate::config_file::ConfigFile;
+use crate::labels::LabelKeys;
+use crate::typos;
+use crate::utils::serializers;
+
 const DEFAULT_DOCKER_HOST: &str = "/var/run/docker.sock";
 
-#[derive(Parser, Debug)]
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Exits with 0 when the running instance is healthy, 1 otherwise, through `AUTOHEAL_HTTP_ADDRESS`.
+    Healthcheck,
+    /// Checks once, restarts what is unhealthy, sends the notifications and exits, with 1 when Docker couldn't be reached, or 2 when a restart failed.
+    Once,
+    /// Lists the running containers the label filter lets through, with what a check would do with them right now.
+    List,
+    /// Restarts a container by name or id, like a check would, with the same stop timeout, notifications and audit records.
+    Heal {
+        /// Name or id of the container.
+        container: String,
+    },
+    /// Shows the unhealthy containers, pauses and exclusions of the running instance, through `AUTOHEAL_HTTP_ADDRESS`.
+    Status,
+    /// Validates the settings, connects to Docker and lists the containers that would be watched, exits with 1 on any problem.
+    CheckConfig {
+        #[arg(long, help = "Also send a test notification to `WEBHOOK_URL`")]
+        test_notification: bool,
+    },
+}
+
+#[derive(Parser, Debug, Serialize)]
 struct RawConfig {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
+    #[arg(long, help = "Same as the `once` command")]
+    #[serde(skip)]
+    pub once: bool,
+
+    #[arg(
+        long,
+        env = "AUTOHEAL_CONFIG",
+        help = "TOML or YAML file with settings, flags and environment variables take precedence over it, reloaded on `SIGHUP`"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        env = "AUTOHEAL_CONFIG_WATCH_INTERVAL",
+        default_value = "0",
+        long,
+        value_parser = parse_duration,
+        help = "How often to check `--config` for changes, and reload it when it did, 0 to only reload on `SIGHUP`"
+    )]
+    #[serde(serialize_with = "serializers::duration")]
+    pub config_watch_interval: Duration,
+
     #[arg(env, default_value = DEFAULT_DOCKER_HOST, value_parser = parse_docker_host, help = "Path to docker TCP/UNIX socket", long="docker")]
+    #[serde(serialize_with = "serializers::display")]
     pub docker_host: Endpoint,
 
     #[arg(long, env)]
@@ -22,30 +81,42 @@ struct RawConfig {
         env,
         default_value = "10",
         long,
-        help = "When container is unhealthy, how long to wait for it to stop, before forcefully restarting it, in seconds",
+        help = "When container is unhealthy, how long to wait for it to stop, before forcefully restarting it, like `10s` or `1m`, bare numbers are seconds",
         value_parser = parse_duration
     )]
+    #[serde(serialize_with = "serializers::duration")]
     pub autoheal_default_stop_timeout: Duration,
 
     #[arg(
         env,
         default_value = "5",
         long,
-        help = "Interval between checks, in seconds",
+        help = "Interval between checks, like `500ms` or `1m30s`, bare numbers are seconds",
         value_parser = parse_duration
     )]
+    #[serde(serialize_with = "serializers::duration")]
     pub autoheal_interval: Duration,
 
     #[arg(long, env)]
     pub autoheal_exclude_containers: Vec<String>,
 
+    #[arg(
+        env,
+        default_value = "autoheal",
+        long,
+        value_parser = parse_label_prefix,
+        help = "Prefix of the labels read on containers, like `<prefix>.stop.timeout`, to run several instances side by side"
+    )]
+    pub autoheal_label_prefix: String,
+
     #[arg(
         env,
         default_value = "0",
-        help = "Startup timeout, in seconds",
+        help = "Startup timeout, like `30s` or `2m`, bare numbers are seconds",
         value_parser = parse_duration,
         long,
    )]
+    #[serde(serialize_with = "serializers::duration")]
     pub autoheal_start_period: Duration,
 
     #[arg(long, env = "CA")]
@@ -61,31 +132,149 @@ struct RawConfig {
         env = "timeout",
         default_value = "30",
         long,
-        help = "Docker socket timeout, in seconds, only used when connecting over tcp",
+        help = "Docker socket timeout, like `30s`, bare numbers are seconds, only used when connecting over tcp",
         value_parser = parse_duration
     )]
+    #[serde(serialize_with = "serializers::duration")]
     pub timeout: Duration,
 
-    #[arg(long, env)]
+    #[arg(long, env, hide_env_values = true)]
+    #[serde(serialize_with = "serializers::redacted_uri")]
     pub webhook_url: Option<Uri>,
-}
 
-impl RawConfig {
-    pub fn print(&self) {
-        event!(Level::INFO, docker_host = %self.docker_host, "Daemon");
-    }
+    #[arg(
+        env,
+        default_value = "0",
+        long,
+        help = "Collapse repeated notifications for the same container within this window, like `5m`, bare numbers are seconds, 0 to disable",
+        value_parser = parse_duration
+    )]
+    #[serde(serialize_with = "serializers::duration")]
+    pub autoheal_notification_deduplication_window: Duration,
+
+    #[arg(
+        env,
+        default_value = "0",
+        long,
+        help = "Instead of notifying immediately, send a summary of all handled containers at this interval, like `1h`, bare numbers are seconds, 0 to disable",
+        value_parser = parse_duration
+    )]
+    #[serde(serialize_with = "serializers::duration")]
+    pub autoheal_notification_digest_interval: Duration,
+
+    #[arg(
+        env,
+        default_value = "0",
+        long,
+        help = "Maximum number of notifications sent per minute, 0 for unlimited"
+    )]
+    pub autoheal_notification_rate_limit: usize,
+
+    #[arg(
+        env,
+        default_value = "false",
+        long,
+        help = "Also notify the webhook when autoheal starts and stops",
+        action = ArgAction::Set
+    )]
+    pub autoheal_notify_lifecycle: bool,
+
+    #[arg(
+        env,
+        default_value = "false",
+        long,
+        help = "Fail on `AUTOHEAL_*` environment variables that aren't settings, instead of warning about them",
+        action = ArgAction::Set
+    )]
+    pub autoheal_strict: bool,
+
+    #[arg(
+        long,
+        env,
+        hide_env_values = true,
+        help = "Pinged after every successful check, `/start`, `/fail` and `/<exit code>` are appended to signal the other events"
+    )]
+    #[serde(serialize_with = "serializers::redacted_uri")]
+    pub autoheal_heartbeat_url: Option<Uri>,
+
+    #[arg(
+        env,
+        default_value = "3",
+        long,
+        help = "Number of consecutive failures to list containers after which the heartbeat reports a failure, at least 1"
+    )]
+    pub autoheal_heartbeat_failure_threshold: NonZeroUsize,
+
+    #[arg(
+        long,
+        env,
+        help = "Address to listen on for HTTP requests, e.g. `0.0.0.0:9090`, serves `/metrics`, `/healthz`, `/containers`, `/events`, `/stats` and `/config`"
+    )]
+    pub autoheal_http_address: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        env,
+        hide_env_values = true,
+        help = "Bearer token required to pause, resume, heal and exclude containers over HTTP, these routes are disabled without one"
+    )]
+    #[serde(serialize_with = "serializers::redacted")]
+    pub autoheal_api_token: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        help = "File to keep unhealthy containers, recent events, pauses and exclusions in, so they survive a restart"
+    )]
+    pub autoheal_state_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        help = "File to append a JSON line to for every decision made, e.g. restarts, skips and notifications"
+    )]
+    pub autoheal_audit_log: Option<PathBuf>,
+
+    #[arg(
+        env,
+        default_value = "10485760",
+        long,
+        help = "Size in bytes after which the audit log is rotated"
+    )]
+    pub autoheal_audit_log_max_size: u64,
+
+    #[arg(
+        env,
+        default_value = "5",
+        long,
+        help = "Number of rotated audit logs to keep"
+    )]
+    pub autoheal_audit_log_max_files: usize,
 }
 
-fn parse_docker_host(value: &str) -> Result<Endpoint, String> {
+pub fn parse_docker_host(value: &str) -> Result<Endpoint, String> {
     Endpoint::from_str(value)
 }
 
-fn parse_duration(value: &str) -> Result<Duration, String> {
-    let seconds = value
-        .parse()
-        .map_err(|error| format!("Could not parse `{}`: {}", value, error))?;
+/// Bare numbers are seconds, as they've always been, anything else is read by `humantime`, like `500ms` or `1m30s`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
 
-    Ok(Duration::from_secs(seconds))
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    humantime::parse_duration(value)
+        .map_err(|error| format!("Could not parse `{}`: {}", value, error))
+}
+
+/// Trailing dots are trimmed later on, so nothing but dots would leave keys like `.stop.timeout`.
+pub fn parse_label_prefix(value: &str) -> Result<String, String> {
+    if value.trim_matches('.').is_empty() {
+        return Err("The label prefix can't be empty".to_owned());
+    }
+
+    Ok(value.to_owned())
 }
 
 pub struct DockerConfig {
@@ -96,25 +285,192 @@ pub struct DockerConfig {
     pub timeout: Duration,
 }
 
+impl DockerConfig {
+    pub fn client(self) -> Result<Client, eyre::Report> {
+        Client::build(
+            self.docker_host,
+            self.cacert,
+            self.client_cert,
+            self.client_key,
+            self.timeout,
+        )
+    }
+}
+
 pub struct HealerConfig {
     pub default_stop_timeout: Duration,
     pub interval: Duration,
     pub exclude_containers: Box<[Box<str>]>,
     pub start_period: Duration,
+    pub state_file: Option<PathBuf>,
+    pub label_keys: LabelKeys,
+}
+
+#[derive(Debug)]
+pub struct NotifierConfig {
+    pub webhook_url: Option<Uri>,
+    pub deduplication_window: Duration,
+    pub digest_interval: Duration,
+    pub rate_limit: usize,
+    pub lifecycle: bool,
+}
+
+pub struct HeartbeatConfig {
+    pub url: Option<Uri>,
+    pub failure_threshold: usize,
+}
+
+pub struct ApiConfig {
+    pub address: Option<SocketAddr>,
+    pub token: Option<Box<str>>,
+}
+
+pub struct AuditConfig {
+    pub path: Option<PathBuf>,
+    pub max_size: u64,
+    pub max_files: usize,
 }
 
 pub struct AppConfig {
+    /// What to do instead of healing, if anything.
+    pub command: Option<Command>,
+    /// Where the settings came from, if a file, to reload them from.
+    pub config_file: Option<PathBuf>,
+    pub config_watch_interval: Duration,
+    /// What we ended up with after parsing, with secrets redacted.
+    pub effective_config: JsonValue,
     pub container_label: Option<String>,
     pub docker_config: DockerConfig,
     pub healer_config: HealerConfig,
-    pub webhook_url: Option<Uri>,
+    pub notifier_config: NotifierConfig,
+    pub heartbeat_config: HeartbeatConfig,
+    pub api_config: ApiConfig,
+    pub audit_config: AuditConfig,
+    /// Whether unknown `AUTOHEAL_*` variables are an error rather than a warning.
+    pub strict: bool,
+}
+
+/// Just what the `healthcheck` command needs, which a container's `HEALTHCHECK` runs every few seconds.
+pub struct HealthcheckConfig {
+    pub address: Option<SocketAddr>,
+}
+
+impl HealthcheckConfig {
+    /// Skips the config file, the `_FILE` variables and everything that logs, returns `None` for every other command.
+    pub fn build() -> Option<HealthcheckConfig> {
+        let matches = RawConfig::command()
+            .ignore_errors(true)
+            .try_get_matches()
+            .ok()?;
+
+        let _name = matches
+            .subcommand_name()
+            .filter(|&name| name == "healthcheck")?;
+
+        Some(HealthcheckConfig {
+            address: matches
+                .get_one::<SocketAddr>("autoheal_http_address")
+                .copied(),
+        })
+    }
+}
+
+/// `--config` has to be known before parsing the rest, as the file provides their defaults.
+fn config_file_path(command: &clap::Command) -> Option<PathBuf> {
+    command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches()
+        .ok()?
+        .get_one::<PathBuf>("config")
+        .cloned()
+}
+
+/// Secrets in Docker and Swarm end up in files, so every variable can also be given as `<NAME>_FILE`, pointing to a file with the value.
+///
+/// `lookup` reads an environment variable, `<NAME>` itself still wins when both are set.
+fn env_file_defaults<F: Fn(&OsStr) -> Option<OsString>>(
+    command: &clap::Command,
+    lookup: F,
+) -> Result<Vec<(String, Vec<String>)>, eyre::Report> {
+    let mut defaults = Vec::new();
+
+    for arg in command.get_arguments() {
+        let Some(env) = arg.get_env() else {
+            continue;
+        };
+
+        let mut file_env = env.to_owned();
+        file_env.push("_FILE");
+
+        let Some(path) = lookup(&file_env).map(PathBuf::from) else {
+            continue;
+        };
+
+        if lookup(env).is_some() {
+            event!(
+                Level::WARN,
+                variable = %env.to_string_lossy(),
+                "Both the variable and its `_FILE` variant are set, ignoring the file"
+            );
+
+            continue;
+        }
+
+        let value = std::fs::read_to_string(&path).map_err(|error| {
+            eyre::Report::new(error).wrap_err(format!(
+                "Failed to read `{}` from {}",
+                file_env.to_string_lossy(),
+                path.display()
+            ))
+        })?;
+
+        defaults.push((arg.get_id().to_string(), vec![value.trim().to_owned()]));
+    }
+
+    Ok(defaults)
+}
+
+/// Flags and environment variables still win over these, as they do over any default.
+fn with_defaults<I: AsRef<str>>(
+    command: clap::Command,
+    defaults: Vec<(I, Vec<String>)>,
+) -> clap::Command {
+    defaults.into_iter().fold(command, |command, (id, values)| {
+        // they're actual settings, possibly secret ones, rather than defaults worth showing in `--help`
+        command.mut_arg(id, |arg| {
+            arg.default_values(values).hide_default_value(true)
+        })
+    })
 }
 
 impl AppConfig {
+    /// Not part of [`AppConfig::build`], as the environment doesn't change on a reload, and neither would the warnings.
+    pub fn check_environment(&self) -> Result<(), eyre::Report> {
+        typos::check_environment(&RawConfig::command(), self.strict)
+    }
+
     pub fn build() -> Result<AppConfig, eyre::Report> {
-        let raw_config = RawConfig::try_parse()?;
+        let env_files = env_file_defaults(&RawConfig::command(), |name| std::env::var_os(name))?;
+
+        let mut command = with_defaults(RawConfig::command(), env_files.clone());
+
+        if let Some(path) = config_file_path(&command) {
+            // the `_FILE` variables stand in for environment variables, so they win over the file
+            command = with_defaults(
+                with_defaults(command, ConfigFile::load(&path)?.into_defaults()),
+                env_files,
+            );
+        }
 
-        raw_config.print();
+        let raw_config = RawConfig::from_arg_matches(&command.try_get_matches()?)?;
+
+        // a timer can't tick every 0s, and now that `1ms` is an option, there's no need for it
+        if raw_config.autoheal_interval.is_zero() {
+            return Err(eyre::Report::msg("`AUTOHEAL_INTERVAL` must be more than 0"));
+        }
+
+        let effective_config = to_json_value(&raw_config)?;
 
         let docker_config = DockerConfig {
             docker_host: raw_config.docker_host,
@@ -133,13 +489,208 @@ impl AppConfig {
                 .map(String::into_boxed_str)
                 .collect::<Box<[_]>>(),
             start_period: raw_config.autoheal_start_period,
+            state_file: raw_config.autoheal_state_file,
+            label_keys: LabelKeys::new(&raw_config.autoheal_label_prefix),
+        };
+
+        let notifier_config = NotifierConfig {
+            webhook_url: raw_config.webhook_url,
+            deduplication_window: raw_config.autoheal_notification_deduplication_window,
+            digest_interval: raw_config.autoheal_notification_digest_interval,
+            rate_limit: raw_config.autoheal_notification_rate_limit,
+            lifecycle: raw_config.autoheal_notify_lifecycle,
+        };
+
+        let heartbeat_config = HeartbeatConfig {
+            url: raw_config.autoheal_heartbeat_url,
+            failure_threshold: raw_config.autoheal_heartbeat_failure_threshold.get(),
+        };
+
+        let api_config = ApiConfig {
+            address: raw_config.autoheal_http_address,
+            token: raw_config.autoheal_api_token.map(String::into_boxed_str),
+        };
+
+        let audit_config = AuditConfig {
+            path: raw_config.autoheal_audit_log,
+            max_size: raw_config.autoheal_audit_log_max_size,
+            max_files: raw_config.autoheal_audit_log_max_files,
         };
 
         Ok(AppConfig {
+            command: raw_config
+                .command
+                .or(raw_config.once.then_some(Command::Once)),
+            config_file: raw_config.config,
+            config_watch_interval: raw_config.config_watch_interval,
+            effective_config,
             docker_config,
             healer_config,
+            notifier_config,
+            heartbeat_config,
+            api_config,
+            audit_config,
             container_label: raw_config.autoheal_container_label,
-            webhook_url: raw_config.webhook_url,
+            strict: raw_config.autoheal_strict,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::time::Duration;
+
+    use clap::{CommandFactory as _, FromArgMatches as _};
+    use hashbrown::HashMap;
+    use pretty_assertions::assert_eq;
+
+    use crate::config::{RawConfig, env_file_defaults, parse_duration, with_defaults};
+    use crate::config_file::ConfigFile;
+    use crate::utils::test_directory::TestDirectory;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("soon").is_err(), "Not a duration");
+    }
+
+    #[test]
+    fn reads_file_variants() {
+        let directory = TestDirectory::new("env-file");
+
+        let path = directory.join("webhook_url");
+        std::fs::write(&path, "https://ntfy.sh/secret\n").unwrap();
+
+        let environment = HashMap::<_, _>::from_iter([
+            (
+                OsString::from("WEBHOOK_URL_FILE"),
+                path.clone().into_os_string(),
+            ),
+            (
+                OsString::from("AUTOHEAL_INTERVAL_FILE"),
+                path.into_os_string(),
+            ),
+            (OsString::from("AUTOHEAL_INTERVAL"), OsString::from("3")),
+        ]);
+
+        let defaults =
+            env_file_defaults(&RawConfig::command(), |name| environment.get(name).cloned())
+                .unwrap();
+
+        assert_eq!(
+            defaults,
+            vec![(
+                "webhook_url".to_owned(),
+                vec!["https://ntfy.sh/secret".to_owned()]
+            )],
+            "Trimmed, and only when the variable itself isn't set"
+        );
+    }
+
+    #[test]
+    fn hides_injected_defaults_from_help() {
+        let help = with_defaults(
+            RawConfig::command(),
+            vec![("webhook_url", vec!["https://ntfy.sh/secret".to_owned()])],
+        )
+        .render_long_help()
+        .to_string();
+
+        assert!(!help.contains("secret"), "Secrets stay out of `--help`");
+    }
+
+    #[test]
+    fn rejects_zero_failure_threshold() {
+        let result = RawConfig::command().try_get_matches_from([
+            "autoheal-rs",
+            "--autoheal-heartbeat-failure-threshold",
+            "0",
+        ]);
+
+        assert!(
+            result.is_err(),
+            "The heartbeat would never report a failure"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_label_prefix() {
+        for prefix in ["", ".", ".."] {
+            let result = RawConfig::command().try_get_matches_from([
+                "autoheal-rs",
+                "--autoheal-label-prefix",
+                prefix,
+            ]);
+
+            assert!(result.is_err(), "`{}` leaves no prefix", prefix);
+        }
+    }
+
+    #[test]
+    fn flags_win_over_config_file() {
+        let config_file = ConfigFile::from_toml(
+            r#"
+            [docker]
+            host = "tcp://docker:2375"
+            ca = "/certs/ca.pem"
+            client_key = "/certs/key.pem"
+            client_cert = "/certs/cert.pem"
+            timeout = 20
+
+            [healer]
+            container_label = "autoheal"
+            default_stop_timeout = 5
+            interval = 10
+            exclude_containers = ["db"]
+            start_period = 60
+            state_file = "/var/lib/autoheal/state.json"
+
+            [notifier]
+            webhook_url = "https://ntfy.sh/autoheal"
+            deduplication_window = 300
+            digest_interval = 3600
+            rate_limit = 10
+            lifecycle = true
+
+            [heartbeat]
+            url = "https://hc-ping.com/uuid"
+            failure_threshold = 5
+
+            [api]
+            address = "0.0.0.0:9090"
+            token = "secret"
+
+            [audit]
+            log = "/var/log/autoheal.jsonl"
+            max_size = 1024
+            max_files = 2
+            "#,
+        )
+        .unwrap();
+
+        let command = with_defaults(RawConfig::command(), config_file.into_defaults());
+
+        let raw_config = RawConfig::from_arg_matches(
+            &command
+                .try_get_matches_from(["autoheal-rs", "--autoheal-interval", "3"])
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(raw_config.autoheal_interval, Duration::from_secs(3));
+        assert_eq!(raw_config.autoheal_start_period, Duration::from_secs(60));
+        assert_eq!(
+            raw_config.autoheal_exclude_containers,
+            vec!["db".to_owned()]
+        );
+        assert_eq!(raw_config.autoheal_audit_log_max_files, 2);
+        assert!(
+            raw_config.autoheal_notify_lifecycle,
+            "Booleans are taken from the file"
+        );
+    }
+}
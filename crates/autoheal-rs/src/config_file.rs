@@ -0,0 +1,402 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use color_eyre::eyre;
+use hyper::Uri;
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::config::{parse_docker_host, parse_duration, parse_label_prefix};
+
+/// Settings read from `--config`, every one of them optional, and named after the section of [`crate::config::AppConfig`] they end up in.
+///
+/// Values are checked with the same parser as their flag, so that mistakes are reported with the line they're on,
+/// and then handed to clap as text, as defaults that flags and environment variables override.
+///
+/// Every key takes the same values as the environment variable next to it:
+///
+/// ```toml
+/// [docker]
+/// host = "unix:///var/run/docker.sock"  # DOCKER_HOST
+/// ca = "/certs/ca.pem"                  # CA
+/// client_key = "/certs/key.pem"         # CLIENT_KEY
+/// client_cert = "/certs/cert.pem"       # CLIENT_CERT
+/// timeout = "10s"                       # timeout
+///
+/// [healer]
+/// container_label = "autoheal"          # AUTOHEAL_CONTAINER_LABEL
+/// default_stop_timeout = "10s"          # AUTOHEAL_DEFAULT_STOP_TIMEOUT
+/// interval = "5s"                       # AUTOHEAL_INTERVAL
+/// exclude_containers = ["db"]           # AUTOHEAL_EXCLUDE_CONTAINERS
+/// label_prefix = "autoheal"             # AUTOHEAL_LABEL_PREFIX
+/// start_period = "0s"                   # AUTOHEAL_START_PERIOD
+/// state_file = "/data/state.json"       # AUTOHEAL_STATE_FILE
+///
+/// [notifier]
+/// webhook_url = "https://ntfy.sh/x"     # WEBHOOK_URL
+/// deduplication_window = "5m"           # AUTOHEAL_NOTIFICATION_DEDUPLICATION_WINDOW
+/// digest_interval = "0s"                # AUTOHEAL_NOTIFICATION_DIGEST_INTERVAL
+/// rate_limit = 0                        # AUTOHEAL_NOTIFICATION_RATE_LIMIT
+/// lifecycle = true                      # AUTOHEAL_NOTIFY_LIFECYCLE
+///
+/// [heartbeat]
+/// url = "https://hc-ping.com/x"         # AUTOHEAL_HEARTBEAT_URL
+/// failure_threshold = 3                 # AUTOHEAL_HEARTBEAT_FAILURE_THRESHOLD
+///
+/// [api]
+/// address = "127.0.0.1:8080"            # AUTOHEAL_HTTP_ADDRESS
+/// token = "secret"                      # AUTOHEAL_API_TOKEN
+///
+/// [audit]
+/// log = "/data/audit.jsonl"             # AUTOHEAL_AUDIT_LOG
+/// max_size = 10485760                   # AUTOHEAL_AUDIT_LOG_MAX_SIZE
+/// max_files = 5                         # AUTOHEAL_AUDIT_LOG_MAX_FILES
+/// ```
+///
+/// YAML files have the same sections and keys. Settings that differ per container, like where its notifications go,
+/// are container labels, not part of this file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    docker: DockerSection,
+    healer: HealerSection,
+    notifier: NotifierSection,
+    heartbeat: HeartbeatSection,
+    api: ApiSection,
+    audit: AuditSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DockerSection {
+    #[serde(deserialize_with = "docker_host")]
+    host: Option<String>,
+    #[serde(deserialize_with = "text")]
+    ca: Option<String>,
+    #[serde(deserialize_with = "text")]
+    client_key: Option<String>,
+    #[serde(deserialize_with = "text")]
+    client_cert: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    timeout: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HealerSection {
+    #[serde(deserialize_with = "text")]
+    container_label: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    default_stop_timeout: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    interval: Option<String>,
+    exclude_containers: Option<Vec<String>>,
+    #[serde(deserialize_with = "label_prefix")]
+    label_prefix: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    start_period: Option<String>,
+    #[serde(deserialize_with = "text")]
+    state_file: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NotifierSection {
+    #[serde(deserialize_with = "uri")]
+    webhook_url: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    deduplication_window: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    digest_interval: Option<String>,
+    #[serde(deserialize_with = "number")]
+    rate_limit: Option<String>,
+    #[serde(deserialize_with = "boolean")]
+    lifecycle: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HeartbeatSection {
+    #[serde(deserialize_with = "uri")]
+    url: Option<String>,
+    #[serde(deserialize_with = "number")]
+    failure_threshold: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ApiSection {
+    #[serde(deserialize_with = "socket_address")]
+    address: Option<String>,
+    #[serde(deserialize_with = "text")]
+    token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AuditSection {
+    #[serde(deserialize_with = "text")]
+    log: Option<String>,
+    #[serde(deserialize_with = "number")]
+    max_size: Option<String>,
+    #[serde(deserialize_with = "number")]
+    max_files: Option<String>,
+}
+
+/// Takes strings, numbers and booleans alike, as the formats differ in what needs quotes.
+struct ScalarVisitor;
+
+impl Visitor<'_> for ScalarVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a string, number or boolean")
+    }
+
+    fn visit_bool<E: Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.to_owned())
+    }
+}
+
+fn validated<'de, D: Deserializer<'de>, E: fmt::Display, V: FnOnce(&str) -> Result<(), E>>(
+    deserializer: D,
+    validate: V,
+) -> Result<Option<String>, D::Error> {
+    let value = deserializer.deserialize_any(ScalarVisitor)?;
+
+    validate(&value).map_err(<D::Error as Error>::custom)?;
+
+    Ok(Some(value))
+}
+
+fn text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |_| Ok::<_, String>(()))
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| parse_duration(value).map(|_| ()))
+}
+
+fn docker_host<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| parse_docker_host(value).map(|_| ()))
+}
+
+fn label_prefix<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| parse_label_prefix(value).map(|_| ()))
+}
+
+fn uri<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| value.parse::<Uri>().map(|_| ()))
+}
+
+fn socket_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| {
+        value.parse::<SocketAddr>().map(|_| ())
+    })
+}
+
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| value.parse::<u64>().map(|_| ()))
+}
+
+fn boolean<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    validated(deserializer, |value| value.parse::<bool>().map(|_| ()))
+}
+
+impl ConfigFile {
+    /// Picks the format based on the extension.
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        let contents = std::fs::read_to_string(path).map_err(|error| {
+            eyre::Report::new(error).wrap_err(format!("Failed to read `{}`", path.display()))
+        })?;
+
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        let result = match extension {
+            Some("toml") => Self::from_toml(&contents),
+            Some("yaml" | "yml") => Self::from_yaml(&contents),
+            _ => Err(eyre::Report::msg(
+                "Unknown format, use a `.toml`, `.yaml` or `.yml` extension",
+            )),
+        };
+
+        result.map_err(|error| error.wrap_err(format!("Invalid config file `{}`", path.display())))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, eyre::Report> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    pub fn from_yaml(contents: &str) -> Result<Self, eyre::Report> {
+        // an empty file is an empty document, which YAML considers to be `null`
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_yaml_ng::from_str(contents)?)
+    }
+
+    /// The values that are set, keyed by the id of their argument in [`crate::config::RawConfig`].
+    pub fn into_defaults(self) -> Vec<(&'static str, Vec<String>)> {
+        let ConfigFile {
+            docker,
+            healer,
+            notifier,
+            heartbeat,
+            api,
+            audit,
+        } = self;
+
+        [
+            ("docker_host", docker.host),
+            ("cacert", docker.ca),
+            ("client_key", docker.client_key),
+            ("client_cert", docker.client_cert),
+            ("timeout", docker.timeout),
+            ("autoheal_container_label", healer.container_label),
+            ("autoheal_default_stop_timeout", healer.default_stop_timeout),
+            ("autoheal_interval", healer.interval),
+            ("autoheal_label_prefix", healer.label_prefix),
+            ("autoheal_start_period", healer.start_period),
+            ("autoheal_state_file", healer.state_file),
+            ("webhook_url", notifier.webhook_url),
+            (
+                "autoheal_notification_deduplication_window",
+                notifier.deduplication_window,
+            ),
+            (
+                "autoheal_notification_digest_interval",
+                notifier.digest_interval,
+            ),
+            ("autoheal_notification_rate_limit", notifier.rate_limit),
+            ("autoheal_notify_lifecycle", notifier.lifecycle),
+            ("autoheal_heartbeat_url", heartbeat.url),
+            (
+                "autoheal_heartbeat_failure_threshold",
+                heartbeat.failure_threshold,
+            ),
+            ("autoheal_http_address", api.address),
+            ("autoheal_api_token", api.token),
+            ("autoheal_audit_log", audit.log),
+            ("autoheal_audit_log_max_size", audit.max_size),
+            ("autoheal_audit_log_max_files", audit.max_files),
+        ]
+        .into_iter()
+        .filter_map(|(id, value)| value.map(|value| (id, vec![value])))
+        .chain(
+            healer
+                .exclude_containers
+                .map(|exclude_containers| ("autoheal_exclude_containers", exclude_containers)),
+        )
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config_file::ConfigFile;
+
+    #[test]
+    fn toml_defaults() {
+        let config_file = ConfigFile::from_toml(
+            r#"
+            [healer]
+            interval = 10
+            exclude_containers = ["db", "cache"]
+
+            [notifier]
+            webhook_url = "https://ntfy.sh/autoheal"
+            lifecycle = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config_file.into_defaults(),
+            vec![
+                ("autoheal_interval", vec!["10".to_owned()]),
+                ("webhook_url", vec!["https://ntfy.sh/autoheal".to_owned()]),
+                ("autoheal_notify_lifecycle", vec!["true".to_owned()]),
+                (
+                    "autoheal_exclude_containers",
+                    vec!["db".to_owned(), "cache".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn yaml_defaults() {
+        let config_file = ConfigFile::from_yaml(
+            "
+docker:
+  host: tcp://docker:2375
+audit:
+  log: /var/log/autoheal.jsonl
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config_file.into_defaults(),
+            vec![
+                ("docker_host", vec!["tcp://docker:2375".to_owned()]),
+                (
+                    "autoheal_audit_log",
+                    vec!["/var/log/autoheal.jsonl".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn toml_error_has_line() {
+        let error = ConfigFile::from_toml("[healer]\n\ninterval = \"soon\"\n").unwrap_err();
+
+        let message = error.to_string();
+
+        assert!(message.contains("line 3"), "Missing line in: {}", message);
+        assert!(
+            message.contains("Could not parse `soon`"),
+            "Missing reason in: {}",
+            message
+        );
+    }
+
+    #[test]
+    fn yaml_error_has_line() {
+        let error = ConfigFile::from_yaml("api:\n  address: localhost\n").unwrap_err();
+
+        let message = error.to_string();
+
+        assert!(message.contains("line 2"), "Missing line in: {}", message);
+    }
+
+    #[test]
+    fn unknown_setting_is_an_error() {
+        assert!(
+            ConfigFile::from_toml("[healer]\nintervall = 10\n").is_err(),
+            "Typos are reported"
+        );
+    }
+
+    #[test]
+    fn empty_yaml() {
+        assert_eq!(ConfigFile::from_yaml("").unwrap().into_defaults(), vec![]);
+    }
+}
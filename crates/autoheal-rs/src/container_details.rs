@@ -0,0 +1,148 @@
+use http::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use twistlock::endpoint::ApiEndpoint;
+
+/// One run of a container's healthcheck, as Docker keeps the last few.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "PascalCase"))]
+pub struct HealthProbe {
+    pub start: Box<str>,
+    pub end: Box<str>,
+    pub exit_code: i64,
+    pub output: Box<str>,
+}
+
+/// What the container list doesn't tell us, but what we want to have on record.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawContainerInspect")]
+pub struct ContainerDetails {
+    pub image: Box<str>,
+    pub health_log: Box<[HealthProbe]>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawContainerInspect {
+    config: RawConfig,
+    state: RawState,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawConfig {
+    image: Box<str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawState {
+    health: Option<RawHealth>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawHealth {
+    #[serde(default)]
+    log: Option<Box<[HealthProbe]>>,
+}
+
+impl ContainerDetails {
+    /// What the last failed healthcheck printed, if anything.
+    pub fn failure_reason(&self) -> Option<&str> {
+        self.health_log
+            .iter()
+            .rev()
+            .find(|probe| probe.exit_code != 0)
+            .map(|probe| probe.output.trim())
+            .filter(|output| !output.is_empty())
+    }
+}
+
+impl From<RawContainerInspect> for ContainerDetails {
+    fn from(raw: RawContainerInspect) -> Self {
+        Self {
+            image: raw.config.image,
+            health_log: raw
+                .state
+                .health
+                .and_then(|health| health.log)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// `twistlock`'s `InspectContainer` leaves out the image and the health log.
+pub struct InspectContainerDetails;
+
+impl ApiEndpoint for InspectContainerDetails {
+    type Request = str;
+    type Response = ContainerDetails;
+    type Error = JsonValue;
+
+    const METHOD: Method = Method::GET;
+
+    fn path_and_query(request: &Self::Request) -> Result<String, std::io::Error> {
+        Ok(format!("/containers/{}/json", request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::from_str as from_json_str;
+
+    use crate::container_details::{ContainerDetails, HealthProbe};
+
+    #[test]
+    fn parse_details() {
+        let details = from_json_str::<ContainerDetails>(
+            r#"{
+                "Id": "aaaaaaaaaaaa",
+                "Config": { "Image": "nginx:latest", "Labels": {} },
+                "State": {
+                    "Status": "running",
+                    "Health": {
+                        "Status": "unhealthy",
+                        "FailingStreak": 1,
+                        "Log": [
+                            {
+                                "Start": "2026-10-18T17:00:00.000000000Z",
+                                "End": "2026-10-18T17:00:01.000000000Z",
+                                "ExitCode": 1,
+                                "Output": "connection refused"
+                            }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            details,
+            ContainerDetails {
+                image: "nginx:latest".into(),
+                health_log: Box::new([HealthProbe {
+                    start: "2026-10-18T17:00:00.000000000Z".into(),
+                    end: "2026-10-18T17:00:01.000000000Z".into(),
+                    exit_code: 1,
+                    output: "connection refused".into(),
+                }]),
+            }
+        );
+        assert_eq!(details.failure_reason(), Some("connection refused"));
+    }
+
+    #[test]
+    fn parse_details_without_healthcheck() {
+        let details = from_json_str::<ContainerDetails>(
+            r#"{ "Config": { "Image": "nginx:latest" }, "State": { "Status": "running" } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(details.image, "nginx:latest".into());
+        assert!(details.health_log.is_empty(), "There is no health log");
+        assert_eq!(details.failure_reason(), None);
+    }
+}
@@ -1,33 +1,66 @@
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
 
-use hashbrown::HashMap;
+use color_eyre::eyre;
+use hashbrown::{HashMap, HashSet};
 use http::Uri;
-use tokio::time::{MissedTickBehavior, sleep};
-use tracing::{Level, event};
+use serde_json::Value as JsonValue;
+use tokio::sync::watch;
+use tokio::time::{Instant, Interval, MissedTickBehavior, sleep};
+use tracing::{Instrument as _, Level, event, span};
 use twistlock::client::Client;
 use twistlock::filters::Filters;
 use twistlock::models::container::Container;
 
-use crate::config::HealerConfig;
-use crate::webhook::WebHookNotifier;
+use crate::audit::{AuditAction, AuditLog, AuditRecord};
+use crate::config::{HealerConfig, parse_duration};
+use crate::container_details::{ContainerDetails, InspectContainerDetails};
+use crate::endpoints::ListAllContainers;
+use crate::heartbeat::Heartbeat;
+use crate::labels::LabelKeys;
+use crate::metrics::{COMPOSE_SERVICE_LABEL, ContainerLabels, Metrics};
+use crate::reliability::ReliabilityStats;
+use crate::state::{EventKind, HealEvent, HealerState, LastCheck, TrackedContainer};
+use crate::state_file::{self, StateFile};
+use crate::typos;
+use crate::webhook::{NotifyTarget, Route, WebHookNotifier};
+
+/// What can change without a restart, swapped as a whole when the configuration is reloaded.
+pub struct HealerSettings {
+    pub effective_config: JsonValue,
+    pub healer_config: HealerConfig,
+    pub filters: Filters,
+}
 
 pub struct DockerHealer {
+    audit: AuditLog,
     client: Client,
-    filters: Filters,
-    healer_config: HealerConfig,
+    heartbeat: Heartbeat,
+    metrics: Arc<Metrics>,
     notifier: WebHookNotifier,
+    settings: watch::Sender<Arc<HealerSettings>>,
+    started_at: Instant,
+    state: Mutex<HealerState>,
+    state_file: Option<StateFile>,
+    /// Containers we've warned about unknown labels for, while they stay unhealthy.
+    warned_labels: Mutex<HashSet<Box<str>>>,
+    /// Containers we've warned about invalid notification labels for, which can't change for a container.
+    warned_routes: Mutex<HashSet<Box<str>>>,
 }
 
-fn get_timeout(labels: &HashMap<Box<str>, Box<str>>) -> Option<Duration> {
-    if let Some(timeout) = labels.get("autoheal.stop.timeout") {
-        match timeout.parse::<u64>() {
-            Ok(value) => Some(Duration::from_secs(value)),
+pub fn get_timeout(
+    labels: &HashMap<Box<str>, Box<str>>,
+    label_keys: &LabelKeys,
+) -> Option<Duration> {
+    if let Some(timeout) = labels.get(&label_keys.stop_timeout) {
+        match parse_duration(timeout) {
+            Ok(value) => Some(value),
             Err(error) => {
                 event!(
                     Level::WARN,
                     ?timeout,
                     ?error,
-                    "Could not parse timeout as `u64`"
+                    "Could not parse timeout as a duration"
                 );
 
                 None
@@ -38,22 +71,302 @@ fn get_timeout(labels: &HashMap<Box<str>, Box<str>>) -> Option<Duration> {
     }
 }
 
+/// The route from the labels, with what was wrong with them, to warn about once.
+fn get_notify_route(
+    labels: &HashMap<Box<str>, Box<str>>,
+    label_keys: &LabelKeys,
+) -> (Route, Vec<String>) {
+    let mut problems = Vec::new();
+
+    let uri = labels
+        .get(&label_keys.notify_url)
+        .and_then(|uri| match uri.parse::<Uri>() {
+            Ok(value) => Some(value),
+            Err(error) => {
+                problems.push(format!("Could not parse notify url `{}`: {}", uri, error));
+
+                None
+            },
+        });
+
+    let target = labels
+        .get(&label_keys.notify_target)
+        .and_then(|target| match target.parse::<NotifyTarget>() {
+            Ok(value) => Some(value),
+            Err(error) => {
+                problems.push(format!(
+                    "Could not parse notify target `{}`: {}",
+                    target, error
+                ));
+
+                None
+            },
+        })
+        .unwrap_or_default();
+
+    if target == NotifyTarget::Container && uri.is_none() {
+        problems.push(
+            "Notify target is `container`, but there is no valid notify url, notifying the global webhook instead"
+                .to_owned(),
+        );
+
+        return (Route::default(), problems);
+    }
+
+    (Route { uri, target }, problems)
+}
+
+fn check_interval(period: Duration) -> Interval {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    interval
+}
+
+fn heal_event(container_info: &Container, kind: EventKind, reason: Option<&str>) -> HealEvent {
+    HealEvent {
+        at: SystemTime::now(),
+        container_id: container_info.id.clone(),
+        container_name: container_info.get_name().map(Into::into),
+        kind,
+        reason: reason.map(Into::into),
+    }
+}
+
+fn log_reliability_stats(
+    kind: &str,
+    name: &str,
+    compose_service: Option<&str>,
+    stats: &ReliabilityStats,
+) {
+    event!(
+        Level::INFO,
+        kind,
+        name,
+        compose_service,
+        restarts_24h = stats.restarts_24h,
+        restarts_7d = stats.restarts_7d,
+        mean_time_between_failures = stats
+            .mean_time_between_failures
+            .map(|duration| humantime::format_duration(duration).to_string()),
+        mean_time_to_recovery = stats
+            .mean_time_to_recovery
+            .map(|duration| humantime::format_duration(duration).to_string()),
+        last_failure_at = stats
+            .last_failure_at
+            .map(|at| humantime::format_rfc3339_seconds(at).to_string()),
+        last_failure_reason = stats.last_failure_reason.as_deref(),
+        "Reliability",
+    );
+}
+
 impl DockerHealer {
     pub fn new(
         client: Client,
-        healer_config: HealerConfig,
-        filters: Filters,
-        webhook_uri: Option<Uri>,
+        settings: HealerSettings,
+        notifier: WebHookNotifier,
+        heartbeat: Heartbeat,
+        metrics: Arc<Metrics>,
+        audit: AuditLog,
     ) -> Self {
+        // where the state is kept can't change while we're running
+        let state_file = settings
+            .healer_config
+            .state_file
+            .clone()
+            .map(StateFile::new);
+
         Self {
+            audit,
             client,
-            filters,
-            healer_config,
-            notifier: WebHookNotifier { uri: webhook_uri },
+            heartbeat,
+            metrics,
+            notifier,
+            settings: watch::Sender::new(Arc::new(settings)),
+            started_at: Instant::now(),
+            state: Mutex::new(HealerState::default()),
+            state_file,
+            warned_labels: Mutex::new(HashSet::new()),
+            warned_routes: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, HealerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn settings(&self) -> Arc<HealerSettings> {
+        Arc::clone(&self.settings.borrow())
+    }
+
+    /// Takes effect from the next check on, or right away when the interval changed.
+    pub fn reconfigure(&self, settings: HealerSettings) {
+        self.settings.send_replace(Arc::new(settings));
+    }
+
+    /// Whether the monitor loop keeps going, and can talk to Docker.
+    pub fn liveness(&self) -> Result<(), &'static str> {
+        let settings = self.settings();
+        let healer_config = &settings.healer_config;
+
+        // a check can take a while when it restarts containers
+        let max_age = healer_config.interval * 3 + healer_config.default_stop_timeout;
+
+        match self.state().last_check {
+            None if self.started_at.elapsed() < healer_config.start_period + max_age => Ok(()),
+            None => Err("No check completed yet"),
+            Some(last_check) if last_check.at.elapsed() > max_age => {
+                Err("Last check completed too long ago")
+            },
+            Some(LastCheck {
+                docker_reachable: false,
+                ..
+            }) => Err("Docker API was unreachable during the last check"),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Picks up where the previous instance left off, minus the containers that are gone since.
+    async fn restore_state(&self) {
+        let Some(ref state_file) = self.state_file else {
+            return;
+        };
+
+        let mut persisted = match state_file.load() {
+            Ok(Some(persisted)) => persisted,
+            Ok(None) => return,
+            Err(error) => {
+                event!(
+                    Level::WARN,
+                    ?error,
+                    "Failed to load state, starting from scratch"
+                );
+
+                return;
+            },
+        };
+
+        if !persisted.containers.is_empty() {
+            match self
+                .existing_containers(persisted.containers.keys().cloned().collect())
+                .await
+            {
+                Ok(existing) => {
+                    let removed = persisted.reconcile(&existing);
+
+                    if removed > 0 {
+                        event!(
+                            Level::INFO,
+                            removed,
+                            "Forgot about containers that no longer exist"
+                        );
+                    }
+                },
+                Err(error) => {
+                    // the first check will report them as recovered instead
+                    event!(Level::WARN, ?error, "Could not reconcile state");
+                },
+            }
+        }
+
+        event!(
+            Level::INFO,
+            path = %state_file.path().display(),
+            containers = persisted.containers.len(),
+            "Restored state"
+        );
+
+        persisted.restore_into(&mut self.state());
+    }
+
+    async fn save_state(&self) {
+        let Some(ref state_file) = self.state_file else {
+            return;
+        };
+
+        // encoded on its own so the state isn't locked while we write it out
+        let contents = match state_file::encode(&self.state()) {
+            Ok(contents) => contents,
+            Err(error) => {
+                event!(Level::WARN, ?error, "Failed to encode state");
+
+                return;
+            },
+        };
+
+        let state_file = state_file.clone();
+
+        let result = tokio::task::spawn_blocking(move || state_file.save(&contents))
+            .await
+            .map_err(eyre::Report::new)
+            .and_then(|result| result);
+
+        if let Err(error) = result {
+            event!(Level::WARN, ?error, "Failed to save state");
+        }
+    }
+
+    /// Keeps the event around for the API, and puts it on record.
+    fn record(&self, event: HealEvent, details: Option<&ContainerDetails>) {
+        self.audit.write(&AuditRecord::from_event(&event, details));
+
+        self.state().record(event);
+    }
+
+    /// Only fetched when we need them for the audit log, as it's another call to Docker.
+    async fn container_details(&self, container_id: &str) -> Option<ContainerDetails> {
+        if self.audit.is_enabled() {
+            self.inspect(container_id).await
+        } else {
+            None
         }
     }
 
-    pub async fn check_container_health(&self, container_info: &Container, times: usize) {
+    async fn inspect(&self, container_id: &str) -> Option<ContainerDetails> {
+        match self
+            .client
+            .call::<InspectContainerDetails>(container_id)
+            .await
+        {
+            Ok(details) => Some(details),
+            Err(error) => {
+                event!(Level::WARN, ?error, %container_id, "Failed to inspect container");
+
+                self.metrics.docker_api_error("inspect_container");
+
+                None
+            },
+        }
+    }
+
+    /// Which of `ids` still exist, running or not.
+    async fn existing_containers(
+        &self,
+        ids: HashSet<Box<str>>,
+    ) -> Result<HashSet<Box<str>>, eyre::Report> {
+        let filters = Filters {
+            id: Some(ids),
+            ..Filters::default()
+        };
+
+        match self.client.call::<ListAllContainers>(&filters).await {
+            Ok(containers) => Ok(containers
+                .into_iter()
+                .map(|container| container.id)
+                .collect()),
+            Err(error) => {
+                self.metrics.docker_api_error("list_containers");
+
+                Err(eyre::Report::new(error).wrap_err("Failed to list containers"))
+            },
+        }
+    }
+
+    pub async fn check_container_health(
+        &self,
+        container_info: &Container,
+        times: usize,
+    ) -> HealEvent {
         let container_short_id = container_info.get_short_id();
 
         match container_info.get_name() {
@@ -63,6 +376,12 @@ impl DockerHealer {
                     %container_short_id,
                     "Container name was null, which implies container does not exist - don't restart.",
                 );
+
+                heal_event(
+                    container_info,
+                    EventKind::Skipped,
+                    Some("Container has no name"),
+                )
             },
             Some(container_name) => {
                 if &*container_info.state == "restarting" {
@@ -72,118 +391,695 @@ impl DockerHealer {
                         %container_short_id,
                         "Container found to be restarting - don't restart.",
                     );
+
+                    heal_event(
+                        container_info,
+                        EventKind::Skipped,
+                        Some("Container is restarting"),
+                    )
                 } else {
-                    let timeout = get_timeout(&container_info.labels)
-                        .unwrap_or(self.healer_config.default_stop_timeout);
+                    let healer_config = &self.settings().healer_config;
+
+                    let timeout = get_timeout(&container_info.labels, &healer_config.label_keys)
+                        .unwrap_or(healer_config.default_stop_timeout);
 
                     event!(
                         Level::INFO,
                         %container_name,
                         %container_short_id,
-                        times_unhealthy = %times,
+                        times_unhealthy = times,
                         timeout = ?timeout,
                         "Container repeatedly found to be unhealthy. Restarting container now with timeout.",
                     );
 
-                    match self
-                        .client
-                        .restart_container(container_short_id, timeout)
+                    self.restart(container_info, container_name, timeout, None)
                         .await
-                    {
-                        Ok(()) => {
-                            self.notifier
-                                .notify_webhook_success(container_short_id, container_name);
-                        },
-                        Err(error) => {
-                            event!(
-                                Level::WARN,
-                                ?error,
-                                %container_name,
-                                %container_short_id,
-                                "Restarting container failed.",
-                            );
-
-                            self.notifier.notify_webhook_failure(
-                                container_name,
-                                container_short_id,
-                                error.into(),
-                            );
-                        },
-                    }
                 }
             },
         }
     }
 
-    pub async fn monitor_containers(&self) -> ! {
-        if self.healer_config.start_period.as_secs() > 0 {
+    /// By its exact name first, then by its id, or the start of it, like `docker` does.
+    async fn find_container(&self, container: &str) -> Result<Option<Container>, eyre::Report> {
+        let filters = Filters {
+            name: Some(HashSet::from_iter([container.into()])),
+            ..Filters::default()
+        };
+
+        // the name filter matches on parts of the name as well
+        if let Some(container_info) = self
+            .client
+            .list_containers(&filters)
+            .await?
+            .into_iter()
+            .find(|container_info| container_info.names.iter().any(|name| &**name == container))
+        {
+            return Ok(Some(container_info));
+        }
+
+        let filters = Filters {
+            id: Some(HashSet::from_iter([container.into()])),
+            ..Filters::default()
+        };
+
+        let mut matches = self
+            .client
+            .list_containers(&filters)
+            .await?
+            .into_iter()
+            .filter(|container_info| container_info.id.starts_with(container))
+            .collect::<Vec<_>>();
+
+        if matches.len() > 1 {
+            return Err(eyre::Report::msg(format!(
+                "`{}` matches more than one container",
+                container
+            )));
+        }
+
+        Ok(matches.pop())
+    }
+
+    /// Restarts a container by name or id, regardless of its health, pauses or exclusions.
+    ///
+    /// Returns `None` when there is no running container with that name or id.
+    pub async fn heal(
+        &self,
+        container: &str,
+        reason: &str,
+    ) -> Result<Option<EventKind>, eyre::Report> {
+        let Some(container_info) = self.find_container(container).await? else {
+            return Ok(None);
+        };
+
+        let container_name = container_info.get_name().unwrap_or(container);
+
+        let healer_config = &self.settings().healer_config;
+
+        let timeout = get_timeout(&container_info.labels, &healer_config.label_keys)
+            .unwrap_or(healer_config.default_stop_timeout);
+
+        event!(
+            Level::INFO,
+            %container_name,
+            container_short_id = %container_info.get_short_id(),
+            timeout = ?timeout,
+            %reason,
+            "Restarting container on request.",
+        );
+
+        let details = self.container_details(&container_info.id).await;
+
+        let event = self
+            .restart(&container_info, container_name, timeout, Some(reason))
+            .await;
+
+        let kind = event.kind;
+
+        self.record(event, details.as_ref());
+
+        Ok(Some(kind))
+    }
+
+    async fn restart(
+        &self,
+        container_info: &Container,
+        container_name: &str,
+        timeout: Duration,
+        reason: Option<&str>,
+    ) -> HealEvent {
+        let container_short_id = container_info.get_short_id();
+
+        let (route, problems) = get_notify_route(
+            &container_info.labels,
+            &self.settings().healer_config.label_keys,
+        );
+
+        if !problems.is_empty()
+            && self
+                .warned_routes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(container_info.id.clone())
+        {
+            for problem in problems {
+                event!(
+                    Level::WARN,
+                    %container_name,
+                    %container_short_id,
+                    %problem,
+                    "Invalid notification label"
+                );
+            }
+        }
+
+        let metric_labels = ContainerLabels::new(container_name, &container_info.labels);
+
+        self.metrics.restart_attempted(&metric_labels);
+
+        // Docker only takes whole seconds, and rounding down would turn `500ms` into killing it right away
+        let timeout = Duration::from_secs(
+            timeout
+                .as_secs()
+                .saturating_add(u64::from(timeout.subsec_nanos() > 0)),
+        );
+
+        let start = Instant::now();
+
+        let result = self
+            .client
+            .restart_container(container_short_id, timeout)
+            .instrument(span!(Level::INFO, "restart_container", timeout = ?timeout))
+            .await;
+
+        self.metrics
+            .restart_finished(&metric_labels, result.is_ok(), start.elapsed());
+
+        match result {
+            Ok(()) => {
+                self.notifier
+                    .notify_webhook_success(container_short_id, container_name, route);
+
+                heal_event(container_info, EventKind::Restarted, reason)
+            },
+            Err(error) => {
+                event!(
+                    Level::WARN,
+                    ?error,
+                    %container_name,
+                    %container_short_id,
+                    "Restarting container failed.",
+                );
+
+                self.metrics.docker_api_error("restart_container");
+
+                let event = heal_event(
+                    container_info,
+                    EventKind::RestartFailed,
+                    Some(&error.to_string()),
+                );
+
+                self.notifier.notify_webhook_failure(
+                    container_name,
+                    container_short_id,
+                    route,
+                    error.into(),
+                );
+
+                event
+            },
+        }
+    }
+
+    async fn check_tracked_container(
+        &self,
+        container: &Container,
+        tracked: &mut TrackedContainer,
+        now: SystemTime,
+    ) {
+        tracked.times_unhealthy += 1;
+
+        let first_time = tracked.times_unhealthy == 1;
+
+        // the first time we want the failure reason, whether the audit log needs the details or not
+        let details = if first_time {
+            self.inspect(&container.id).await
+        } else {
+            self.container_details(&container.id).await
+        };
+
+        if first_time {
+            self.audit.write(&AuditRecord::new(
+                AuditAction::Unhealthy,
+                &container.id,
+                container.get_name(),
+                details.as_ref(),
+            ));
+
+            if let Some(container_name) = container.get_name() {
+                self.state().reliability.failure(
+                    container_name,
+                    container
+                        .labels
+                        .get(COMPOSE_SERVICE_LABEL)
+                        .map(|service| &**service),
+                    now,
+                    details.as_ref().and_then(ContainerDetails::failure_reason),
+                );
+            }
+        }
+
+        let skip_reason = {
+            let settings = self.settings();
+            let state = self.state();
+
+            tracked.excluded = container
+                .names
+                .iter()
+                .any(|n| settings.healer_config.exclude_containers.contains(n))
+                || state.is_excluded(&container.names);
+
+            if tracked.excluded {
+                Some("Container is excluded")
+            } else {
+                state.pause_reason(&container.names, now)
+            }
+        };
+
+        let event = if let Some(reason) = skip_reason {
             event!(
                 Level::INFO,
-                delay = ?self.healer_config.start_period,
-                "Monitoring containers for unhealthy status",
+                container_name = %container
+                    .get_name()
+                    .unwrap_or("<UNNAMED CONTAINER>"),
+                container_short_id = %container.get_short_id(),
+                %reason,
+                "Container is unhealthy, but we're leaving it alone",
             );
 
-            sleep(self.healer_config.start_period).await;
+            heal_event(container, EventKind::Skipped, Some(reason))
+        } else {
+            self.check_container_health(container, tracked.times_unhealthy)
+                .await
+        };
+
+        tracked.last_action = Some(event.kind);
+
+        self.record(event, details.as_ref());
+    }
+
+    /// Once per container, or every check would repeat it.
+    fn warn_unknown_labels(&self, container: &Container) {
+        let settings = self.settings();
+
+        let unknown = typos::unknown_labels(
+            container.labels.keys().map(AsRef::as_ref),
+            &settings.healer_config.label_keys,
+            &settings.filters,
+        );
+
+        if unknown.is_empty()
+            || !self
+                .warned_labels
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(container.id.clone())
+        {
+            return;
+        }
+
+        for (label, suggestion) in unknown {
+            event!(
+                Level::WARN,
+                container_name = container.get_name(),
+                container_short_id = container.get_short_id(),
+                %label,
+                did_you_mean = suggestion,
+                "Unknown label"
+            );
         }
+    }
 
-        let mut history_unhealthy = HashMap::<Box<str>, (Option<Box<str>>, usize)>::new();
+    /// Goes over every container the label filter lets through once, as a typo is just as likely on a healthy one.
+    ///
+    /// After that, the checks look at the containers they fetch anyway.
+    async fn check_labels(&self) {
+        let filters = Filters {
+            label: self.settings().filters.label.clone(),
+            ..Filters::default()
+        };
 
-        let mut interval = tokio::time::interval(self.healer_config.interval);
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        match self.client.list_containers(&filters).await {
+            Ok(containers) => {
+                for container in &containers {
+                    self.warn_unknown_labels(container);
+                }
+            },
+            Err(error) => {
+                // the first check reports Docker being unreachable
+                event!(
+                    Level::DEBUG,
+                    ?error,
+                    "Failed to list containers to check their labels"
+                );
 
-        loop {
-            interval.tick().await;
+                self.metrics.docker_api_error("list_containers");
+            },
+        }
+    }
 
-            match self.client.list_containers(&self.filters).await {
-                Ok(containers) => {
-                    let mut new_history =
-                        HashMap::<Box<str>, (Option<Box<str>>, usize)>::with_capacity(
-                            containers.len(),
-                        );
+    async fn check_containers(&self, containers: Vec<Container>) {
+        let mut new_history =
+            HashMap::<Box<str>, TrackedContainer>::with_capacity(containers.len());
 
-                    for container in containers {
-                        let times = history_unhealthy
-                            .get(&container.id)
-                            .map_or(1, |&(_, t)| t + 1);
-
-                        if container
-                            .names
-                            .iter()
-                            .any(|n| self.healer_config.exclude_containers.contains(n))
-                        {
-                            event!(
-                                Level::INFO,
-                                container_name = %container
-                                    .get_name()
-                                    .unwrap_or("<UNNAMED CONTAINER>"),
-                                container_short_id = %container.get_short_id(),
-                                "Container is unhealthy, but it is excluded",
-                            );
-                        } else {
-                            self.check_container_health(&container, times).await;
-                        }
-
-                        let name = container.get_name().map(Into::into);
-                        new_history.insert(container.id, (name, times));
-                    }
+        let now = SystemTime::now();
 
-                    for (key, (name, _)) in history_unhealthy {
-                        if !new_history.contains_key(&key) {
-                            event!(
-                                Level::INFO,
-                                container_name = %name.as_deref().unwrap_or("<UNNAMED CONTAINER>"),
-                                container_id = %key,
-                                "Container returned to healthy state.",
-                            );
-                        }
-                    }
+        self.state().expire_pauses(now);
+
+        for container in containers {
+            self.warn_unknown_labels(&container);
+
+            // clone, so that the API keeps seeing the previous check until this one is done
+            let mut tracked = self
+                .state()
+                .containers
+                .get(&container.id)
+                .cloned()
+                .unwrap_or_else(|| {
+                    TrackedContainer::new(
+                        container.id.clone(),
+                        container.get_name().map(Into::into),
+                    )
+                });
 
-                    history_unhealthy = new_history;
+            let span = span!(
+                Level::INFO,
+                "check_container",
+                container_name = container.get_name(),
+                container_short_id = container.get_short_id(),
+            );
+
+            self.check_tracked_container(&container, &mut tracked, now)
+                .instrument(span)
+                .await;
+
+            tracked.last_action_at = Some(SystemTime::now());
+
+            new_history.insert(container.id, tracked);
+        }
+
+        let recovered = {
+            let mut state = self.state();
+
+            let history_unhealthy = std::mem::replace(&mut state.containers, new_history);
+
+            self.warned_labels
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|id| state.containers.contains_key(id));
+
+            history_unhealthy
+                .into_iter()
+                .filter(|&(ref key, _)| !state.containers.contains_key(key))
+                .collect::<Vec<_>>()
+        };
+
+        // removed containers can't be inspected for the audit log, and that's not worth a warning
+        let existing = if self.audit.is_enabled() && !recovered.is_empty() {
+            self.existing_containers(recovered.iter().map(|&(ref key, _)| key.clone()).collect())
+                .await
+                .ok()
+        } else {
+            None
+        };
+
+        for (key, tracked) in recovered {
+            event!(
+                Level::INFO,
+                container_name = %tracked.name.as_deref().unwrap_or("<UNNAMED CONTAINER>"),
+                container_id = %key,
+                "Container returned to healthy state.",
+            );
+
+            if let Some(ref container_name) = tracked.name {
+                self.state().reliability.recovery(
+                    container_name,
+                    now,
+                    now.duration_since(tracked.unhealthy_since)
+                        .unwrap_or_default(),
+                );
+            }
+
+            let details = if existing
+                .as_ref()
+                .is_none_or(|existing| existing.contains(&key))
+            {
+                self.container_details(&key).await
+            } else {
+                None
+            };
+
+            self.record(
+                HealEvent {
+                    at: SystemTime::now(),
+                    container_id: key,
+                    container_name: tracked.name,
+                    kind: EventKind::Recovered,
+                    reason: None,
                 },
-                Err(error) => {
-                    event!(Level::ERROR, ?error, "Failed to fetch container info");
+                details.as_ref(),
+            );
+        }
+
+        self.update_reliability(now);
+    }
+
+    fn update_reliability(&self, now: SystemTime) {
+        let mut state = self.state();
+
+        state.reliability.prune(now);
+
+        self.metrics
+            .set_reliability(state.reliability.containers(now).map(
+                |(container_name, compose_service, stats)| {
+                    (
+                        ContainerLabels::with_compose_service(container_name, compose_service),
+                        stats,
+                    )
                 },
+            ));
+    }
+
+    /// Logs everything we know, for when there is no HTTP port to ask.
+    pub fn log_snapshot(&self) {
+        let now = SystemTime::now();
+
+        {
+            let settings = self.settings();
+            let state = self.state();
+            let queue_depth = self.notifier.queue_depth();
+
+            event!(
+                Level::INFO,
+                config = %settings.effective_config,
+                tracked_containers = state.containers.len(),
+                checks = state.counters.checks,
+                failed_checks = state.counters.failed_checks,
+                restarts = state.counters.restarts,
+                failed_restarts = state.counters.failed_restarts,
+                skips = state.counters.skips,
+                recoveries = state.counters.recoveries,
+                last_check_ago = ?state.last_check.map(|last_check| last_check.at.elapsed()),
+                docker_reachable = state.last_check.map(|last_check| last_check.docker_reachable),
+                next_check_at = state
+                    .next_check_at
+                    .map(|at| humantime::format_rfc3339_seconds(at).to_string()),
+                notifications_queued = queue_depth.queued,
+                notifications_in_digest = queue_depth.in_digest,
+                "Snapshot",
+            );
+
+            if let Some(pause) = state.overrides.paused {
+                event!(
+                    Level::INFO,
+                    until = pause
+                        .until
+                        .map(|until| humantime::format_rfc3339_seconds(until).to_string()),
+                    "Healing is paused",
+                );
+            }
+
+            for (container_name, pause) in &state.overrides.paused_containers {
+                event!(
+                    Level::INFO,
+                    %container_name,
+                    until = pause.until.map(|until| humantime::format_rfc3339_seconds(until).to_string()),
+                    "Healing is paused for container",
+                );
             }
+
+            for container_name in &state.overrides.excluded_containers {
+                event!(Level::INFO, %container_name, "Container is excluded through the API");
+            }
+
+            for container in state.containers.values() {
+                event!(
+                    Level::INFO,
+                    container_name = container.name.as_deref(),
+                    container_id = %container.id,
+                    times_unhealthy = container.times_unhealthy,
+                    unhealthy_for = ?now.duration_since(container.unhealthy_since).unwrap_or_default(),
+                    excluded = container.excluded,
+                    last_action = ?container.last_action,
+                    next_eligible_restart_at = state
+                        .next_eligible_restart_at(container)
+                        .map(|at| humantime::format_rfc3339_seconds(at).to_string()),
+                    "Tracked container",
+                );
+            }
+        }
+
+        self.log_reliability();
+    }
+
+    /// Logs the restart history of every container and compose service that had trouble during the last week.
+    pub fn log_reliability(&self) {
+        let now = SystemTime::now();
+        let state = self.state();
+
+        for (container_name, compose_service, stats) in state.reliability.containers(now) {
+            log_reliability_stats("container", container_name, compose_service, &stats);
+        }
+
+        for (compose_service, stats) in state.reliability.services(now) {
+            log_reliability_stats("service", compose_service, None, &stats);
+        }
+    }
+
+    async fn check_cycle(&self, scheduled: Instant, consecutive_failures: &mut usize) {
+        let settings = self.settings();
+
+        self.metrics.set_loop_lag(scheduled.elapsed());
+
+        self.state().next_check_at = Some(
+            SystemTime::now()
+                + (scheduled + settings.healer_config.interval).duration_since(Instant::now()),
+        );
+
+        match self.client.list_containers(&settings.filters).await {
+            Ok(containers) => {
+                self.metrics.set_unhealthy_containers(containers.len());
+
+                self.check_containers(containers).await;
+
+                self.state().finish_check(true);
+
+                *consecutive_failures = 0;
+
+                self.heartbeat.success();
+            },
+            Err(error) => {
+                event!(Level::ERROR, ?error, "Failed to fetch container info");
+
+                self.metrics.docker_api_error("list_containers");
+
+                self.state().finish_check(false);
+
+                *consecutive_failures += 1;
+
+                self.heartbeat.failure(*consecutive_failures);
+            },
         }
+
+        self.save_state().await;
+    }
+
+    /// A single check, without the start period.
+    pub async fn check_once(&self) {
+        self.restore_state().await;
+
+        self.check_labels().await;
+
+        self.check_cycle(Instant::now(), &mut 0)
+            .instrument(span!(Level::INFO, "check_cycle"))
+            .await;
+    }
+
+    pub async fn monitor_containers(&self) -> ! {
+        self.restore_state().await;
+
+        self.check_labels().await;
+
+        let mut settings = self.settings.subscribe();
+
+        let start_period = settings.borrow_and_update().healer_config.start_period;
+
+        if !start_period.is_zero() {
+            event!(
+                Level::INFO,
+                delay = ?start_period,
+                "Monitoring containers for unhealthy status",
+            );
+
+            sleep(start_period).await;
+        }
+
+        let mut consecutive_failures = 0;
+
+        let mut interval = check_interval(settings.borrow_and_update().healer_config.interval);
+
+        loop {
+            tokio::select! {
+                scheduled = interval.tick() => {
+                    self.check_cycle(scheduled, &mut consecutive_failures)
+                        .instrument(span!(Level::INFO, "check_cycle"))
+                        .await;
+                },
+                Ok(()) = settings.changed() => {
+                    let period = settings.borrow_and_update().healer_config.interval;
+
+                    // starts with a check, so that a long interval doesn't delay the new one
+                    if period != interval.period() {
+                        interval = check_interval(period);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use http::Uri;
+    use pretty_assertions::assert_eq;
+
+    use crate::docker_healer::get_notify_route;
+    use crate::labels::LabelKeys;
+    use crate::webhook::{NotifyTarget, Route};
+
+    fn labels(labels: &[(&str, &str)]) -> HashMap<Box<str>, Box<str>> {
+        labels
+            .iter()
+            .map(|&(key, value)| (key.into(), value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn routes_from_labels() {
+        let (route, problems) = get_notify_route(
+            &labels(&[
+                ("autoheal.notify.url", "https://ntfy.sh/team"),
+                ("autoheal.notify.target", "container"),
+            ]),
+            &LabelKeys::new("autoheal"),
+        );
+
+        assert_eq!(
+            route,
+            Route {
+                uri: Some(Uri::from_static("https://ntfy.sh/team")),
+                target: NotifyTarget::Container,
+            }
+        );
+        assert!(problems.is_empty(), "Valid labels");
+    }
+
+    #[test]
+    fn routes_globally_on_invalid_labels() {
+        let (route, problems) = get_notify_route(
+            &labels(&[
+                ("autoheal.notify.url", "not a url"),
+                ("autoheal.notify.target", "container"),
+            ]),
+            &LabelKeys::new("autoheal"),
+        );
+
+        assert_eq!(route, Route::default());
+        assert_eq!(
+            problems.len(),
+            2,
+            "Both the url and the fallback are reported"
+        );
     }
 }
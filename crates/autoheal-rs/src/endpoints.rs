@@ -0,0 +1,26 @@
+use http::Method;
+use percent_encoding::{NON_ALPHANUMERIC, percent_encode};
+use serde_json::{Value as JsonValue, to_string as to_json_string};
+use twistlock::endpoint::ApiEndpoint;
+use twistlock::filters::Filters;
+use twistlock::models::container::Container;
+
+/// Lists containers whatever their state, which `twistlock` can't, as it always leaves out `all`.
+pub struct ListAllContainers;
+
+impl ApiEndpoint for ListAllContainers {
+    type Request = Filters;
+    type Response = Vec<Container>;
+    type Error = JsonValue;
+
+    const METHOD: Method = Method::GET;
+
+    fn path_and_query(request: &Self::Request) -> Result<String, std::io::Error> {
+        let filters = to_json_string(request)?;
+
+        Ok(format!(
+            "/containers/json?all=1&filters={}",
+            percent_encode(filters.as_bytes(), NON_ALPHANUMERIC)
+        ))
+    }
+}
@@ -0,0 +1,112 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use http::{Request, StatusCode, Uri};
+use http_body_util::{BodyExt as _, Empty};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use tokio::time::timeout;
+use tracing::{Level, event};
+
+use crate::http_client::execute_request;
+use crate::shutdown::Shutdown;
+
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// We can't connect to `0.0.0.0`, but when we listen there, we listen on loopback too.
+fn reachable(mut address: SocketAddr) -> SocketAddr {
+    if address.ip().is_unspecified() {
+        address.set_ip(match address.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        });
+    }
+
+    address
+}
+
+/// Asks the running instance for `path`, whatever the status.
+pub async fn get(address: SocketAddr, path: &str) -> Result<(StatusCode, Bytes), eyre::Report> {
+    let uri = format!("http://{}{}", reachable(address), path).parse::<Uri>()?;
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let request = Request::builder().uri(uri).body(Empty::new())?;
+
+    let response = timeout(HEALTHCHECK_TIMEOUT, execute_request(&client, request)).await??;
+
+    let status = response.status();
+
+    let body = response.into_body().collect().await?.to_bytes();
+
+    Ok((status, body))
+}
+
+pub async fn check(address: SocketAddr) -> Result<(), eyre::Report> {
+    let (status, body) = get(address, "/healthz").await?;
+
+    if status.is_success() {
+        return Ok(());
+    }
+
+    Err(eyre::Report::msg(format!(
+        "{}: {}",
+        status,
+        String::from_utf8_lossy(&body)
+    )))
+}
+
+/// Asks the running instance whether it's alive, for the container's `HEALTHCHECK`, as the image has no shell or curl.
+pub async fn run(address: Option<SocketAddr>) -> Shutdown {
+    let Some(address) = address else {
+        return Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "The healthcheck needs `AUTOHEAL_HTTP_ADDRESS` to reach the running instance",
+        };
+    };
+
+    match check(address).await {
+        Ok(()) => Shutdown::Success,
+        Err(error) => {
+            event!(Level::ERROR, %error, "Healthcheck failed");
+
+            Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Unhealthy",
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::healthcheck::reachable;
+
+    #[test]
+    fn reachable_unspecified() {
+        assert_eq!(
+            reachable("0.0.0.0:9090".parse::<SocketAddr>().unwrap()),
+            "127.0.0.1:9090".parse::<SocketAddr>().unwrap()
+        );
+
+        assert_eq!(
+            reachable("[::]:9090".parse::<SocketAddr>().unwrap()),
+            "[::1]:9090".parse::<SocketAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn reachable_specified() {
+        assert_eq!(
+            reachable("10.0.0.2:9090".parse::<SocketAddr>().unwrap()),
+            "10.0.0.2:9090".parse::<SocketAddr>().unwrap()
+        );
+    }
+}
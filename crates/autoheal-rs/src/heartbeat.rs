@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use color_eyre::eyre;
+use http::Request;
+use http::uri::{PathAndQuery, Uri};
+use http_body_util::Full;
+use hyper::Method;
+use hyper::body::Bytes;
+use tokio::time::timeout;
+use tracing::{Level, event};
+
+use crate::config::HeartbeatConfig;
+use crate::http_client;
+use crate::http_client::{HttpsClient, execute_request};
+use crate::shutdown::Shutdown;
+use crate::signal_handlers::{SIGINT, SIGTERM};
+
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Dead-man's switch, pings an external monitor (healthchecks.io style) so it notices when we stop pinging.
+#[derive(Clone)]
+pub struct Heartbeat {
+    inner: Option<(Uri, HttpsClient)>,
+    failure_threshold: usize,
+}
+
+/// Appends `suffix` as an extra path segment, keeping the query intact.
+fn with_suffix(uri: &Uri, suffix: &str) -> Result<Uri, eyre::Report> {
+    let mut parts = uri.clone().into_parts();
+
+    let path_and_query = match parts.path_and_query {
+        Some(ref path_and_query) => {
+            let path = path_and_query.path().trim_end_matches('/');
+
+            match path_and_query.query() {
+                Some(query) => format!("{}/{}?{}", path, suffix, query),
+                None => format!("{}/{}", path, suffix),
+            }
+        },
+        None => format!("/{}", suffix),
+    };
+
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)?);
+
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// Being asked to stop, like `docker stop` does, is as clean as it gets, and not something to alert on.
+fn exit_suffix(shutdown: &Shutdown) -> String {
+    match *shutdown {
+        Shutdown::Success | Shutdown::Signal(SIGTERM | SIGINT) => "0".to_owned(),
+        Shutdown::Signal(signal) => (u16::from(signal) + 128).to_string(),
+        Shutdown::OperationalFailure { .. } | Shutdown::UnexpectedError(_) => "fail".to_owned(),
+    }
+}
+
+impl Heartbeat {
+    pub fn build(config: HeartbeatConfig) -> Result<Self, eyre::Report> {
+        let inner = match config.url {
+            Some(url) => Some((url, http_client::build_client()?)),
+            None => None,
+        };
+
+        Ok(Self {
+            inner,
+            failure_threshold: config.failure_threshold,
+        })
+    }
+
+    /// Signals that we started.
+    pub async fn start(&self) {
+        self.ping(Some("start"), "Started").await;
+    }
+
+    /// Signals that we successfully talked to Docker.
+    pub fn success(&self) {
+        let heartbeat = self.clone();
+
+        tokio::task::spawn(async move {
+            heartbeat.ping(None, "Listed containers").await;
+        });
+    }
+
+    /// Signals that talking to Docker failed `consecutive_failures` in a row, which reports a failure once we hit the threshold.
+    pub fn failure(&self, consecutive_failures: usize) {
+        if consecutive_failures != self.failure_threshold {
+            return;
+        }
+
+        let heartbeat = self.clone();
+
+        tokio::task::spawn(async move {
+            heartbeat
+                .ping(Some("fail"), "Failed to list containers repeatedly")
+                .await;
+        });
+    }
+
+    /// Signals that we're stopping, and why.
+    pub async fn exit(&self, shutdown: &Shutdown) {
+        self.ping(Some(&exit_suffix(shutdown)), &shutdown.to_string())
+            .await;
+    }
+
+    async fn ping(&self, suffix: Option<&str>, message: &str) {
+        let Some((ref uri, ref client)) = self.inner else {
+            return;
+        };
+
+        let result = async {
+            let uri = match suffix {
+                Some(suffix) => with_suffix(uri, suffix)?,
+                None => uri.clone(),
+            };
+
+            let request = Request::builder()
+                .uri(uri)
+                .method(Method::POST)
+                .body(Full::new(Bytes::from(message.to_owned())))?;
+
+            let response = timeout(HEARTBEAT_TIMEOUT, execute_request(client, request)).await??;
+
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(eyre::Report::msg(format!(
+                    "Heartbeat returned {}",
+                    response.status()
+                )))
+            }
+        }
+        .await;
+
+        match result {
+            Ok(()) => event!(Level::TRACE, ?suffix, "Heartbeat sent"),
+            Err(error) => event!(Level::WARN, ?error, ?suffix, "Failed to send heartbeat"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre;
+    use http::Uri;
+    use pretty_assertions::assert_eq;
+
+    use crate::heartbeat::{exit_suffix, with_suffix};
+    use crate::shutdown::Shutdown;
+    use crate::signal_handlers::SIGTERM;
+
+    #[test]
+    fn suffix_path() {
+        let uri = Uri::from_static("https://hc-ping.com/abc");
+
+        assert_eq!(
+            with_suffix(&uri, "fail").unwrap(),
+            Uri::from_static("https://hc-ping.com/abc/fail")
+        );
+    }
+
+    #[test]
+    fn suffix_trailing_slash() {
+        let uri = Uri::from_static("https://hc-ping.com/abc/");
+
+        assert_eq!(
+            with_suffix(&uri, "start").unwrap(),
+            Uri::from_static("https://hc-ping.com/abc/start")
+        );
+    }
+
+    #[test]
+    fn suffix_keeps_query() {
+        let uri = Uri::from_static("http://kuma:3001/api/push/abc?status=up");
+
+        assert_eq!(
+            with_suffix(&uri, "143").unwrap(),
+            Uri::from_static("http://kuma:3001/api/push/abc/143?status=up")
+        );
+    }
+
+    #[test]
+    fn stopping_on_request_is_clean() {
+        assert_eq!(exit_suffix(&Shutdown::Signal(SIGTERM)), "0");
+        assert_eq!(exit_suffix(&Shutdown::Signal(9)), "137");
+        assert_eq!(
+            exit_suffix(&Shutdown::UnexpectedError(eyre::Report::msg("boom"))),
+            "fail"
+        );
+    }
+}
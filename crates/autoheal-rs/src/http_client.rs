@@ -0,0 +1,45 @@
+use color_eyre::eyre;
+use http::{Request, Response};
+use http_body_util::Full;
+use hyper::body::{Body, Bytes};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::rt::TokioExecutor;
+
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// Builds a client for outgoing HTTP(S) calls, like webhooks, trusting the native roots.
+///
+/// # Errors
+///
+/// When the native roots cannot be loaded.
+pub fn build_client() -> Result<HttpsClient, eyre::Report> {
+    let connector = HttpsConnectorBuilder::new()
+        .with_native_roots()?
+        .https_or_http()
+        .enable_all_versions()
+        .build();
+
+    Ok(Client::builder(TokioExecutor::new()).build(connector))
+}
+
+/// Executes a request on a client.
+///
+/// # Errors
+///
+/// When the request errors.
+pub async fn execute_request<C, B>(
+    client: &Client<C, B>,
+    request: Request<B>,
+) -> Result<Response<hyper::body::Incoming>, hyper_util::client::legacy::Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let response = client.request(request).await?;
+
+    Ok(response)
+}
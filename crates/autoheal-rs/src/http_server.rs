@@ -0,0 +1,55 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use http::{Request, Response};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::api::Api;
+
+/// Accepts connections forever, handing every request to the [`Api`].
+///
+/// Open connections are dropped once `cancellation_token` is cancelled, so they don't keep the [`Api`] alive.
+pub async fn serve(listener: TcpListener, api: Api, cancellation_token: CancellationToken) -> ! {
+    if let Ok(address) = listener.local_addr() {
+        event!(Level::INFO, %address, "Listening for HTTP requests");
+    }
+
+    loop {
+        let (stream, remote_address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                event!(Level::WARN, ?error, "Failed to accept connection");
+
+                // e.g. out of file descriptors, give it some time
+                sleep(Duration::from_millis(100)).await;
+
+                continue;
+            },
+        };
+
+        let api = api.clone();
+        let cancellation_token = cancellation_token.clone();
+
+        tokio::task::spawn(async move {
+            let service = service_fn(move |request: Request<Incoming>| {
+                let api = api.clone();
+
+                async move { Ok::<Response<Full<Bytes>>, Infallible>(api.handle(request).await) }
+            });
+
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+
+            if let Some(Err(error)) = cancellation_token.run_until_cancelled(connection).await {
+                event!(Level::DEBUG, ?error, %remote_address, "Failed to serve connection");
+            }
+        });
+    }
+}
@@ -0,0 +1,57 @@
+/// The per-container labels we read, under `AUTOHEAL_LABEL_PREFIX`.
+#[derive(Clone, Debug)]
+pub struct LabelKeys {
+    pub prefix: Box<str>,
+    pub stop_timeout: Box<str>,
+    pub notify_url: Box<str>,
+    pub notify_target: Box<str>,
+}
+
+impl LabelKeys {
+    pub fn new(prefix: &str) -> Self {
+        // `autoheal.` would otherwise end up as `autoheal..stop.timeout`
+        let prefix = prefix.trim_end_matches('.');
+
+        Self {
+            prefix: prefix.into(),
+            stop_timeout: format!("{}.stop.timeout", prefix).into(),
+            notify_url: format!("{}.notify.url", prefix).into(),
+            notify_target: format!("{}.notify.target", prefix).into(),
+        }
+    }
+
+    pub fn all(&self) -> [&str; 3] {
+        [&self.notify_target, &self.notify_url, &self.stop_timeout]
+    }
+
+    /// Whether `key` is in our namespace, known or not.
+    pub fn is_ours(&self, key: &str) -> bool {
+        key.strip_prefix(&*self.prefix)
+            .is_some_and(|rest| rest.starts_with('.'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::labels::LabelKeys;
+
+    #[test]
+    fn builds_keys_under_prefix() {
+        let label_keys = LabelKeys::new("com.example.autoheal.");
+
+        assert_eq!(
+            &*label_keys.stop_timeout,
+            "com.example.autoheal.stop.timeout"
+        );
+        assert!(
+            label_keys.is_ours("com.example.autoheal.stop.timout"),
+            "Typos are still ours"
+        );
+        assert!(
+            !label_keys.is_ours("com.example.autohealer.stop.timeout"),
+            "Only whole segments"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use hashbrown::HashSet;
+use tracing::{Level, event};
+use twistlock::client::Client;
+use twistlock::filters::{Filters, Health};
+use twistlock::models::container::Container;
+
+use crate::config::{AppConfig, HealerConfig};
+use crate::docker_healer::get_timeout;
+use crate::labels::LabelKeys;
+use crate::metrics::COMPOSE_SERVICE_LABEL;
+use crate::shutdown::Shutdown;
+use crate::unhealthy_filters;
+use crate::utils::table;
+
+/// What a check would do with a container right now, leaving pauses and exclusions made through the API aside.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Restart,
+    SkipExcluded,
+    SkipRestarting,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match *self {
+            Action::None => "none",
+            Action::Restart => "restart",
+            Action::SkipExcluded => "skip (excluded)",
+            Action::SkipRestarting => "skip (restarting)",
+        };
+
+        f.write_str(action)
+    }
+}
+
+/// Follows the order in which the healer decides.
+fn action(unhealthy: bool, state: &str, excluded: bool) -> Action {
+    if !unhealthy {
+        Action::None
+    } else if excluded {
+        Action::SkipExcluded
+    } else if state == "restarting" {
+        Action::SkipRestarting
+    } else {
+        Action::Restart
+    }
+}
+
+/// A container that the label filter lets through.
+pub struct ManagedContainer {
+    pub name: Option<Box<str>>,
+    pub short_id: Box<str>,
+    pub state: Box<str>,
+    pub health: Box<str>,
+    /// The ones that change what we do, sorted by key.
+    pub labels: Vec<(Box<str>, Box<str>)>,
+    pub stop_timeout: Duration,
+    pub excluded: bool,
+    pub action: Action,
+}
+
+fn relevant_labels(
+    container: &Container,
+    label_keys: &LabelKeys,
+    filters: &Filters,
+) -> Vec<(Box<str>, Box<str>)> {
+    let mut labels = container
+        .labels
+        .iter()
+        .filter(|&(key, _)| {
+            label_keys.is_ours(key)
+                || &**key == COMPOSE_SERVICE_LABEL
+                || filters
+                    .label
+                    .as_ref()
+                    .is_some_and(|label| label.contains_key(key))
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<Vec<_>>();
+
+    labels.sort_unstable();
+
+    labels
+}
+
+/// Lists once per health status, as the container list doesn't tell.
+pub async fn managed_containers(
+    client: &Client,
+    healer_config: &HealerConfig,
+    container_label: Option<&str>,
+) -> Result<Vec<ManagedContainer>, eyre::Report> {
+    let label = unhealthy_filters::build(container_label).label;
+
+    let mut managed = Vec::new();
+    let mut seen = HashSet::new();
+
+    for health in [
+        Health::Unhealthy,
+        Health::Starting,
+        Health::Healthy,
+        Health::None,
+    ] {
+        let unhealthy = health == Health::Unhealthy;
+        let health_name = Box::<str>::from(health.to_string());
+
+        let filters = Filters {
+            label: label.clone(),
+            health: Some(HashSet::from_iter([health])),
+            ..Filters::default()
+        };
+
+        let containers = client
+            .list_containers(&filters)
+            .await
+            .map_err(|error| eyre::Report::new(error).wrap_err("Failed to list containers"))?;
+
+        for container in containers {
+            // a container could change health in between the calls
+            if !seen.insert(container.id.clone()) {
+                continue;
+            }
+
+            let excluded = container
+                .names
+                .iter()
+                .any(|name| healer_config.exclude_containers.contains(name));
+
+            managed.push(ManagedContainer {
+                name: container.get_name().map(Into::into),
+                short_id: container.get_short_id().into(),
+                labels: relevant_labels(&container, &healer_config.label_keys, &filters),
+                stop_timeout: get_timeout(&container.labels, &healer_config.label_keys)
+                    .unwrap_or(healer_config.default_stop_timeout),
+                action: action(unhealthy, &container.state, excluded),
+                excluded,
+                state: container.state,
+                health: health_name.clone(),
+            });
+        }
+    }
+
+    managed.sort_unstable_by(|left, right| left.name.cmp(&right.name));
+
+    Ok(managed)
+}
+
+/// Shows what the healer would do with the containers that are running right now.
+pub async fn run(config: AppConfig) -> Shutdown {
+    let result = async {
+        let client = config.docker_config.client()?;
+
+        managed_containers(
+            &client,
+            &config.healer_config,
+            config.container_label.as_deref(),
+        )
+        .await
+    }
+    .await;
+
+    let containers = match result {
+        Ok(containers) => containers,
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Failed to list containers");
+
+            return Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to list containers",
+            };
+        },
+    };
+
+    let rows = containers
+        .iter()
+        .map(|container| {
+            vec![
+                container
+                    .name
+                    .as_deref()
+                    .unwrap_or("<UNNAMED CONTAINER>")
+                    .to_owned(),
+                container.short_id.to_string(),
+                container.state.to_string(),
+                container.health.to_string(),
+                humantime::format_duration(container.stop_timeout).to_string(),
+                container.excluded.to_string(),
+                container.action.to_string(),
+                container
+                    .labels
+                    .iter()
+                    .map(|&(ref key, ref value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print!(
+        "{}",
+        table::render(
+            &[
+                "NAME",
+                "ID",
+                "STATE",
+                "HEALTH",
+                "STOP TIMEOUT",
+                "EXCLUDED",
+                "ACTION",
+                "LABELS",
+            ],
+            &rows,
+        )
+    );
+
+    Shutdown::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::list::{Action, action};
+
+    #[test]
+    fn decides_like_the_healer() {
+        assert_eq!(action(true, "running", false), Action::Restart);
+        assert_eq!(
+            action(true, "restarting", true),
+            Action::SkipExcluded,
+            "Exclusions come first"
+        );
+        assert_eq!(action(true, "restarting", false), Action::SkipRestarting);
+        assert_eq!(action(false, "running", true), Action::None);
+    }
+}
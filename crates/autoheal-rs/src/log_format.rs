@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::utils::read_env;
+
+const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+
+/// How we write logs to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Full,
+    Compact,
+    Pretty,
+    /// One object per line, with the event's fields as top-level keys.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match &*s.to_ascii_lowercase() {
+            "full" => LogFormat::Full,
+            "compact" => LogFormat::Compact,
+            "pretty" => LogFormat::Pretty,
+            "json" => LogFormat::Json,
+            _ => {
+                return Err(format!(
+                    "Unknown log format `{}`, expected `full`, `compact`, `pretty` or `json`",
+                    s
+                ));
+            },
+        };
+
+        Ok(format)
+    }
+}
+
+impl LogFormat {
+    /// Reads `LOG_FORMAT`, falling back to [`LogFormat::Full`] when it's missing or invalid, in which case the error is returned too.
+    pub fn from_env() -> (LogFormat, Option<eyre::Report>) {
+        match read_env(LOG_FORMAT_ENV).and_then(|format| {
+            format
+                .map(|f| f.parse())
+                .transpose()
+                .map_err(eyre::Report::msg)
+        }) {
+            Ok(format) => (format.unwrap_or_default(), None),
+            Err(error) => (LogFormat::default(), Some(error)),
+        }
+    }
+
+    pub fn layer<S>(self) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let layer = tracing_subscriber::fmt::layer();
+
+        match self {
+            LogFormat::Full => layer.boxed(),
+            LogFormat::Compact => layer.compact().boxed(),
+            LogFormat::Pretty => layer.pretty().boxed(),
+            LogFormat::Json => layer.json().flatten_event(true).boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::log_format::LogFormat;
+
+    #[test]
+    fn parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("Pretty".parse::<LogFormat>(), Ok(LogFormat::Pretty));
+        assert_eq!("COMPACT".parse::<LogFormat>(), Ok(LogFormat::Compact));
+        assert_eq!("full".parse::<LogFormat>(), Ok(LogFormat::Full));
+    }
+
+    #[test]
+    fn parse_log_format_unknown() {
+        assert!(
+            "yaml".parse::<LogFormat>().is_err(),
+            "`yaml` is not a log format"
+        );
+    }
+}
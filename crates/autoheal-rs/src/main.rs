@@ -1,10 +1,33 @@
+mod api;
+mod audit;
 mod build_env;
+mod check_config;
 mod config;
+mod config_file;
+mod container_details;
 mod docker_healer;
+mod endpoints;
+mod healthcheck;
+mod heartbeat;
 mod helpers;
+mod http_client;
+mod http_server;
+mod labels;
+mod list;
+mod log_format;
+mod metrics;
+mod reliability;
+mod reload;
 mod shutdown;
 mod signal_handlers;
+mod state;
+mod state_file;
+mod status;
+mod syslog;
 mod task_tracker_ext;
+#[cfg(feature = "opentelemetry")]
+mod telemetry;
+mod typos;
 mod unhealthy_filters;
 mod utils;
 mod webhook;
@@ -12,25 +35,42 @@ mod webhook;
 use std::convert::Infallible;
 use std::env;
 use std::env::VarError;
+use std::path::PathBuf;
 use std::process::{ExitCode, Termination as _};
+use std::sync::Arc;
 use std::time::Duration;
 
+use api::Api;
+use audit::{AuditLog, AuditWriter};
 use color_eyre::config::HookBuilder;
 use color_eyre::eyre;
-use config::AppConfig;
-use docker_healer::DockerHealer;
+use config::{
+    AppConfig, AuditConfig, Command, DockerConfig, HealthcheckConfig, HeartbeatConfig,
+    NotifierConfig,
+};
+use docker_healer::{DockerHealer, HealerSettings};
+use heartbeat::Heartbeat;
+use metrics::Metrics;
+#[cfg(feature = "opentelemetry")]
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use state::{EventKind, LastCheck};
 use task_tracker_ext::TaskTrackerExt as _;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
+use tracing::level_filters::LevelFilter;
 use tracing::{Level, event};
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
 use tracing_subscriber::{EnvFilter, Layer as _};
-use twistlock::client::Client;
+use webhook::{NotificationDispatcher, WebHookNotifier};
 
 use crate::build_env::get_build_env;
+use crate::log_format::LogFormat;
 use crate::shutdown::Shutdown;
+use crate::syslog::SyslogLayer;
 use crate::utils::flatten_shutdown_handle;
 use crate::utils::task::spawn_with_name;
 
@@ -58,14 +98,23 @@ fn build_filter() -> (EnvFilter, Option<eyre::Report>) {
     (filter, parsing_error)
 }
 
-fn init_tracing(filter: EnvFilter) -> Result<(), eyre::Report> {
+fn init_tracing(
+    filter: EnvFilter,
+    log_format: LogFormat,
+    syslog: Option<(SyslogLayer, LevelFilter)>,
+    #[cfg(feature = "opentelemetry")] tracer_provider: Option<&SdkTracerProvider>,
+) -> Result<(), eyre::Report> {
     let registry = tracing_subscriber::registry();
 
     #[cfg(feature = "tokio-console")]
     let registry = registry.with(console_subscriber::ConsoleLayer::builder().spawn());
 
+    #[cfg(feature = "opentelemetry")]
+    let registry = registry.with(tracer_provider.map(telemetry::layer));
+
     Ok(registry
-        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+        .with(log_format.layer().with_filter(filter))
+        .with(syslog.map(|(layer, level)| layer.with_filter(level)))
         .with(tracing_error::ErrorLayer::default())
         .try_init()?)
 }
@@ -77,98 +126,113 @@ fn main() -> ExitCode {
         .install()
         .expect("Failed to install panic handler");
 
+    // before tracing, as the OTLP exporter needs it
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .expect("Failed building the Runtime");
+
     let (env_filter, parsing_error) = build_filter();
 
-    init_tracing(env_filter).expect("Failed to set up tracing");
+    let (log_format, log_format_error) = LogFormat::from_env();
+
+    let (syslog, syslog_error) = match syslog::build_from_env() {
+        Ok(syslog) => (syslog, None),
+        Err(error) => (None, Some(error)),
+    };
+
+    #[cfg(feature = "opentelemetry")]
+    let (tracer_provider, telemetry_error) = {
+        let _guard = runtime.enter();
+
+        match telemetry::build_from_env() {
+            Ok(tracer_provider) => (tracer_provider, None),
+            Err(error) => (None, Some(error)),
+        }
+    };
+
+    init_tracing(
+        env_filter,
+        log_format,
+        syslog,
+        #[cfg(feature = "opentelemetry")]
+        tracer_provider.as_ref(),
+    )
+    .expect("Failed to set up tracing");
+
+    let error = parsing_error.or(log_format_error).or(syslog_error);
+
+    #[cfg(feature = "opentelemetry")]
+    let error = error.or(telemetry_error);
 
-    // bubble up the parsing error
-    if let Err(error) = parsing_error.map_or(Ok(()), Err) {
+    // bubble up the parsing errors
+    if let Err(error) = error.map_or(Ok(()), Err) {
         return Err::<Infallible, _>(error).report();
     }
 
-    // initialize the runtime
-    let shutdown: Shutdown = tokio::runtime::Builder::new_multi_thread()
-        .enable_io()
-        .enable_time()
-        .build()
-        .expect("Failed building the Runtime")
-        .block_on(async {
-            // explicitly launch everything in a spawned task
-            // see https://docs.rs/tokio/latest/tokio/attr.main.html#non-worker-async-function
-            let handle = spawn_with_name("main task runner", start_tasks());
+    let shutdown: Shutdown = runtime.block_on(async {
+        // explicitly launch everything in a spawned task
+        // see https://docs.rs/tokio/latest/tokio/attr.main.html#non-worker-async-function
+        let handle = spawn_with_name("main task runner", start_tasks());
 
-            flatten_shutdown_handle(handle).await
-        });
+        flatten_shutdown_handle(handle).await
+    });
+
+    #[cfg(feature = "opentelemetry")]
+    if let Some(ref tracer_provider) = tracer_provider {
+        telemetry::shutdown(tracer_provider);
+    }
 
     shutdown.report()
 }
 
-fn print_header() {
+fn header() -> String {
     const NAME: &str = env!("CARGO_PKG_NAME");
     const VERSION: &str = env!("CARGO_PKG_VERSION");
 
     let build_env = get_build_env();
 
-    event!(
-        Level::INFO,
+    format!(
         "{} v{} - built for {} ({})",
         NAME,
         VERSION,
         build_env.get_target(),
         build_env.get_target_cpu().unwrap_or("base cpu variant"),
-    );
+    )
 }
 
-async fn start_tasks() -> Shutdown {
-    print_header();
-
-    let AppConfig {
-        docker_config,
-        healer_config,
-        container_label,
-        webhook_url,
-    } = match AppConfig::build() {
-        Ok(config) => config,
-        Err(error) => return Shutdown::from(error),
-    };
-
-    let filters = unhealthy_filters::build(container_label.as_deref());
+fn print_header() {
+    event!(Level::INFO, "{}", header());
+}
 
-    let docker_client = match Client::build(
-        docker_config.docker_host,
-        docker_config.cacert,
-        docker_config.client_cert,
-        docker_config.client_key,
-        docker_config.timeout,
-    ) {
-        Ok(client) => client,
-        Err(error) => return Shutdown::from(error),
+fn startup_details(
+    docker_host: &str,
+    container_label: Option<&str>,
+    exclude_containers: &[Box<str>],
+) -> String {
+    let excluded = if exclude_containers.is_empty() {
+        "none".to_owned()
+    } else {
+        exclude_containers.join(", ")
     };
 
-    let docker_healer = DockerHealer::new(docker_client, healer_config, filters, webhook_url);
-
-    let cancellation_token = CancellationToken::new();
-
-    let tasks = TaskTracker::new();
-
-    {
-        let cancellation_token = cancellation_token.clone();
-
-        tasks.spawn_with_name("Monitor", async move {
-            let _guard = cancellation_token.clone().drop_guard();
-
-            cancellation_token
-                .run_until_cancelled(docker_healer.monitor_containers())
-                .await;
-        });
-    }
+    format!(
+        "{}\nDocker host: {}\nContainer label: {}\nExcluded containers: {}",
+        header(),
+        docker_host,
+        container_label.unwrap_or("all"),
+        excluded,
+    )
+}
 
+async fn wait_for_shutdown(cancellation_token: &CancellationToken) -> Shutdown {
     // now we wait forever for either
     // * SIGTERM
     // * CTRL+c (SIGINT)
     // * cancellation of the shutdown token, triggered by another task when it
     //   completes unexpectedly (which means it failed)
-    let shutdown_reason = tokio::select! {
+    tokio::select! {
         biased;
         () = cancellation_token.cancelled() => {
             event!(Level::WARN, "Underlying task stopped, stopping all other tasks");
@@ -184,11 +248,10 @@ async fn start_tasks() -> Shutdown {
         result = signal_handlers::wait_for_sigint() => {
             result
         },
-    };
-
-    // catch all cancel in case we got here via something else than a cancellation token
-    cancellation_token.cancel();
+    }
+}
 
+async fn wait_for_tasks(tasks: &TaskTracker) {
     tasks.close();
 
     // wait for the tasks that holds the server to exit gracefully
@@ -201,6 +264,478 @@ async fn start_tasks() -> Shutdown {
     {
         event!(Level::ERROR, "Task didn't stop within allotted time!");
     }
+}
+
+async fn wait_for_notifications(notification_dispatcher: JoinHandle<()>) {
+    if timeout(Duration::from_secs(10), notification_dispatcher)
+        .await
+        .is_err()
+    {
+        event!(
+            Level::ERROR,
+            "Notifications weren't sent within allotted time!"
+        );
+    }
+}
+
+/// Only finishes once nothing writes to the audit log anymore.
+async fn wait_for_audit(audit_writer: AuditWriter) {
+    if timeout(Duration::from_secs(10), audit_writer.finish())
+        .await
+        .is_err()
+    {
+        event!(
+            Level::ERROR,
+            "Audit log wasn't written within allotted time!"
+        );
+    }
+}
+
+async fn start_tasks() -> Shutdown {
+    if let Some(healthcheck_config) = HealthcheckConfig::build() {
+        return healthcheck::run(healthcheck_config.address).await;
+    }
+
+    let config = match AppConfig::build() {
+        Ok(config) => config,
+        Err(error) => return Shutdown::from(error),
+    };
+
+    event!(Level::INFO, docker_host = %config.docker_config.docker_host, "Daemon");
+
+    if let Err(error) = config.check_environment() {
+        return Shutdown::from(error);
+    }
+
+    match config.command {
+        Some(Command::Healthcheck) => healthcheck::run(config.api_config.address).await,
+        Some(Command::Once) => run_once(config).await,
+        Some(Command::List) => list::run(config).await,
+        Some(Command::Heal { ref container }) => {
+            let container = container.clone();
+
+            run_heal(config, &container).await
+        },
+        Some(Command::Status) => status::run(config.api_config.address).await,
+        Some(Command::CheckConfig { test_notification }) => {
+            check_config::run(config, test_notification).await
+        },
+        None => run_daemon(config).await,
+    }
+}
+
+fn spawn_monitor(
+    tasks: &TaskTracker,
+    cancellation_token: &CancellationToken,
+    docker_healer: Arc<DockerHealer>,
+) {
+    let cancellation_token = cancellation_token.clone();
+
+    tasks.spawn_with_name("Monitor", async move {
+        let _guard = cancellation_token.clone().drop_guard();
+
+        cancellation_token
+            .run_until_cancelled(docker_healer.monitor_containers())
+            .await;
+    });
+}
+
+fn spawn_sigusr1_handler(
+    tasks: &TaskTracker,
+    cancellation_token: &CancellationToken,
+    docker_healer: Arc<DockerHealer>,
+) {
+    let cancellation_token = cancellation_token.clone();
+
+    // not holding a drop guard, failing to listen for it is no reason to stop
+    tasks.spawn_with_name("SIGUSR1 handler", async move {
+        cancellation_token
+            .run_until_cancelled(signal_handlers::on_sigusr1(|| {
+                docker_healer.log_snapshot();
+            }))
+            .await;
+    });
+}
+
+fn spawn_reload_handlers(
+    tasks: &TaskTracker,
+    cancellation_token: &CancellationToken,
+    docker_healer: &Arc<DockerHealer>,
+    notifier: &WebHookNotifier,
+    config_file: Option<PathBuf>,
+    config_watch_interval: Duration,
+) {
+    {
+        let cancellation_token = cancellation_token.clone();
+        let docker_healer = Arc::clone(docker_healer);
+        let notifier = notifier.clone();
+
+        // like SIGUSR1, no drop guard
+        tasks.spawn_with_name("SIGHUP handler", async move {
+            cancellation_token
+                .run_until_cancelled(signal_handlers::on_sighup(|| {
+                    event!(Level::INFO, "SIGHUP received, reloading configuration");
+
+                    reload::reload(Arc::clone(&docker_healer), notifier.clone())
+                }))
+                .await;
+        });
+    }
+
+    if let Some(config_file) = config_file
+        && !config_watch_interval.is_zero()
+    {
+        let cancellation_token = cancellation_token.clone();
+        let docker_healer = Arc::clone(docker_healer);
+        let notifier = notifier.clone();
+
+        tasks.spawn_with_name("Config watcher", async move {
+            cancellation_token
+                .run_until_cancelled(reload::watch(&config_file, config_watch_interval, || {
+                    reload::reload(Arc::clone(&docker_healer), notifier.clone())
+                }))
+                .await;
+        });
+    }
+}
+
+fn spawn_http_server(
+    tasks: &TaskTracker,
+    cancellation_token: &CancellationToken,
+    listener: TcpListener,
+    api: Api,
+) {
+    let cancellation_token = cancellation_token.clone();
+
+    tasks.spawn_with_name("HTTP server", async move {
+        let _guard = cancellation_token.clone().drop_guard();
+
+        cancellation_token
+            .run_until_cancelled(http_server::serve(
+                listener,
+                api,
+                cancellation_token.clone(),
+            ))
+            .await;
+    });
+}
+
+/// What the daemon and a single pass both need.
+struct Components {
+    audit_writer: AuditWriter,
+    docker_healer: Arc<DockerHealer>,
+    heartbeat: Heartbeat,
+    metrics: Arc<Metrics>,
+    notifier: WebHookNotifier,
+    notification_dispatcher: NotificationDispatcher,
+}
+
+fn build_healer(
+    docker_config: DockerConfig,
+    settings: HealerSettings,
+    notifier_config: NotifierConfig,
+    heartbeat_config: HeartbeatConfig,
+    audit_config: AuditConfig,
+) -> Result<Components, eyre::Report> {
+    let docker_client = docker_config.client()?;
+
+    let heartbeat = Heartbeat::build(heartbeat_config)?;
+
+    let (audit, audit_writer) = AuditLog::open(audit_config)?;
+
+    let metrics = Arc::new(Metrics::new());
+
+    let (notifier, notification_dispatcher) =
+        webhook::build(notifier_config, Arc::clone(&metrics), audit.clone());
+
+    let docker_healer = Arc::new(DockerHealer::new(
+        docker_client,
+        settings,
+        notifier.clone(),
+        heartbeat.clone(),
+        Arc::clone(&metrics),
+        audit,
+    ));
+
+    Ok(Components {
+        audit_writer,
+        docker_healer,
+        heartbeat,
+        metrics,
+        notifier,
+        notification_dispatcher,
+    })
+}
+
+/// For the commands that only need the healer, and not the API or the signal handlers.
+fn build_components(config: AppConfig) -> Result<Components, eyre::Report> {
+    let AppConfig {
+        effective_config,
+        docker_config,
+        healer_config,
+        container_label,
+        notifier_config,
+        heartbeat_config,
+        audit_config,
+        ..
+    } = config;
+
+    build_healer(
+        docker_config,
+        HealerSettings {
+            effective_config,
+            healer_config,
+            filters: unhealthy_filters::build(container_label.as_deref()),
+        },
+        notifier_config,
+        heartbeat_config,
+        audit_config,
+    )
+}
+
+/// Restarts that failed are worth telling apart from not being able to check at all.
+const EXIT_RESTART_FAILED: u8 = 2;
+
+/// One check, for when cron or a systemd timer does the scheduling.
+async fn run_once(config: AppConfig) -> Shutdown {
+    print_header();
+
+    let Components {
+        audit_writer,
+        docker_healer,
+        heartbeat,
+        notifier,
+        notification_dispatcher,
+        ..
+    } = match build_components(config) {
+        Ok(components) => components,
+        Err(error) => return Shutdown::from(error),
+    };
+
+    heartbeat.start().await;
+
+    let notification_dispatcher = spawn_with_name("Notifier", notification_dispatcher.run());
+
+    docker_healer.check_once().await;
+
+    let shutdown = {
+        let state = docker_healer.state();
+
+        match state.last_check {
+            Some(LastCheck {
+                docker_reachable: true,
+                ..
+            }) if state.counters.failed_restarts == 0 => Shutdown::Success,
+            Some(LastCheck {
+                docker_reachable: true,
+                ..
+            }) => Shutdown::OperationalFailure {
+                code: ExitCode::from(EXIT_RESTART_FAILED),
+                message: "Failed to restart some containers",
+            },
+            Some(_) | None => Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to list containers",
+            },
+        }
+    };
+
+    // the dispatcher stops once all notifiers are gone, after sending what's still queued
+    drop(docker_healer);
+    drop(notifier);
+
+    wait_for_notifications(notification_dispatcher).await;
+    wait_for_audit(audit_writer).await;
+
+    heartbeat.exit(&shutdown).await;
+
+    shutdown
+}
+
+/// A restart by hand, which goes through the same motions as one a check decides on.
+async fn run_heal(config: AppConfig, container: &str) -> Shutdown {
+    // no heartbeat, it's not a check, and it would confuse whatever watches those
+    let Components {
+        audit_writer,
+        docker_healer,
+        notifier,
+        notification_dispatcher,
+        ..
+    } = match build_components(config) {
+        Ok(components) => components,
+        Err(error) => return Shutdown::from(error),
+    };
+
+    let notification_dispatcher = spawn_with_name("Notifier", notification_dispatcher.run());
+
+    let shutdown = match docker_healer
+        .heal(container, "Requested from the command line")
+        .await
+    {
+        Ok(Some(EventKind::Restarted)) => Shutdown::Success,
+        Ok(Some(_)) => Shutdown::OperationalFailure {
+            code: ExitCode::from(EXIT_RESTART_FAILED),
+            message: "Failed to restart the container",
+        },
+        Ok(None) => Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "No running container with that name or id",
+        },
+        Err(error) => {
+            event!(Level::ERROR, ?error, %container, "Failed to find container to heal");
+
+            Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to list containers",
+            }
+        },
+    };
+
+    // the dispatcher stops once all notifiers are gone, after sending what's still queued
+    drop(docker_healer);
+    drop(notifier);
+
+    wait_for_notifications(notification_dispatcher).await;
+    wait_for_audit(audit_writer).await;
+
+    shutdown
+}
+
+async fn run_daemon(config: AppConfig) -> Shutdown {
+    print_header();
+
+    let AppConfig {
+        config_file,
+        config_watch_interval,
+        effective_config,
+        docker_config,
+        healer_config,
+        container_label,
+        notifier_config,
+        heartbeat_config,
+        api_config,
+        audit_config,
+        ..
+    } = config;
+
+    let startup_details = startup_details(
+        &docker_config.docker_host.to_string(),
+        container_label.as_deref(),
+        &healer_config.exclude_containers,
+    );
+
+    let Components {
+        audit_writer,
+        docker_healer,
+        heartbeat,
+        metrics,
+        notifier,
+        notification_dispatcher,
+    } = match build_healer(
+        docker_config,
+        HealerSettings {
+            effective_config,
+            healer_config,
+            filters: unhealthy_filters::build(container_label.as_deref()),
+        },
+        notifier_config,
+        heartbeat_config,
+        audit_config,
+    ) {
+        Ok(components) => components,
+        // there's no notifier or heartbeat yet to tell
+        Err(error) => return Shutdown::from(error),
+    };
+
+    // not part of `tasks`, as it needs to outlive them to send the shutdown notification
+    let notification_dispatcher = spawn_with_name("Notifier", notification_dispatcher.run());
+
+    heartbeat.start().await;
+
+    let listener = match api_config.address {
+        Some(address) => match TcpListener::bind(address).await {
+            Ok(listener) => Some(listener),
+            Err(error) => {
+                return stop(
+                    Shutdown::from(error),
+                    docker_healer,
+                    notifier,
+                    notification_dispatcher,
+                    audit_writer,
+                    &heartbeat,
+                )
+                .await;
+            },
+        },
+        None => None,
+    };
+
+    notifier.notify_startup(startup_details);
+
+    let cancellation_token = CancellationToken::new();
+
+    let tasks = TaskTracker::new();
+
+    spawn_monitor(&tasks, &cancellation_token, Arc::clone(&docker_healer));
+
+    spawn_sigusr1_handler(&tasks, &cancellation_token, Arc::clone(&docker_healer));
+
+    spawn_reload_handlers(
+        &tasks,
+        &cancellation_token,
+        &docker_healer,
+        &notifier,
+        config_file,
+        config_watch_interval,
+    );
+
+    if let Some(listener) = listener {
+        let api = Api::new(
+            Arc::clone(&docker_healer),
+            Arc::clone(&metrics),
+            api_config.token,
+        );
+
+        spawn_http_server(&tasks, &cancellation_token, listener, api);
+    }
+
+    let shutdown_reason = wait_for_shutdown(&cancellation_token).await;
+
+    // catch all cancel in case we got here via something else than a cancellation token
+    cancellation_token.cancel();
+
+    wait_for_tasks(&tasks).await;
+
+    stop(
+        shutdown_reason,
+        docker_healer,
+        notifier,
+        notification_dispatcher,
+        audit_writer,
+        &heartbeat,
+    )
+    .await
+}
+
+/// Tells the webhook and the heartbeat that we're stopping, once nothing else uses the healer.
+async fn stop(
+    shutdown_reason: Shutdown,
+    docker_healer: Arc<DockerHealer>,
+    notifier: WebHookNotifier,
+    notification_dispatcher: JoinHandle<()>,
+    audit_writer: AuditWriter,
+    heartbeat: &Heartbeat,
+) -> Shutdown {
+    notifier.notify_shutdown(&shutdown_reason);
+
+    // the dispatcher stops once all notifiers are gone, after sending what's still queued
+    drop(docker_healer);
+    drop(notifier);
+
+    wait_for_notifications(notification_dispatcher).await;
+    wait_for_audit(audit_writer).await;
+
+    heartbeat.exit(&shutdown_reason).await;
 
     shutdown_reason
 }
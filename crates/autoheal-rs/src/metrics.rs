@@ -0,0 +1,270 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use hashbrown::{HashMap, HashSet};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
+use prometheus_client::registry::Registry;
+
+use crate::reliability::ReliabilityStats;
+
+pub const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ContainerLabels {
+    container_name: String,
+    compose_service: String,
+}
+
+impl ContainerLabels {
+    pub fn new(container_name: &str, labels: &HashMap<Box<str>, Box<str>>) -> Self {
+        Self::with_compose_service(
+            container_name,
+            labels.get(COMPOSE_SERVICE_LABEL).map(|service| &**service),
+        )
+    }
+
+    pub fn with_compose_service(container_name: &str, compose_service: Option<&str>) -> Self {
+        Self {
+            container_name: container_name.to_owned(),
+            compose_service: compose_service.map(ToOwned::to_owned).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct OperationLabels {
+    operation: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ResultLabels {
+    result: &'static str,
+}
+
+/// Everything we expose on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    restarts_attempted: Family<ContainerLabels, Counter>,
+    restarts_succeeded: Family<ContainerLabels, Counter>,
+    restarts_failed: Family<ContainerLabels, Counter>,
+    unhealthy_containers: Gauge,
+    restart_duration: Histogram,
+    docker_api_errors: Family<OperationLabels, Counter>,
+    webhook_deliveries: Family<ResultLabels, Counter>,
+    loop_lag: Gauge<f64, AtomicU64>,
+    restarts_last_day: Family<ContainerLabels, Gauge>,
+    restarts_last_week: Family<ContainerLabels, Gauge>,
+    mean_time_between_failures: Family<ContainerLabels, Gauge<f64, AtomicU64>>,
+    mean_time_to_recovery: Family<ContainerLabels, Gauge<f64, AtomicU64>>,
+    /// The containers we currently report reliability statistics for.
+    reliability_labels: Mutex<HashSet<ContainerLabels>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("autoheal");
+
+        let restarts_attempted = Family::<ContainerLabels, Counter>::default();
+        registry.register(
+            "restarts_attempted",
+            "Number of container restarts attempted",
+            restarts_attempted.clone(),
+        );
+
+        let restarts_succeeded = Family::<ContainerLabels, Counter>::default();
+        registry.register(
+            "restarts_succeeded",
+            "Number of container restarts that succeeded",
+            restarts_succeeded.clone(),
+        );
+
+        let restarts_failed = Family::<ContainerLabels, Counter>::default();
+        registry.register(
+            "restarts_failed",
+            "Number of container restarts that failed",
+            restarts_failed.clone(),
+        );
+
+        let unhealthy_containers = Gauge::default();
+        registry.register(
+            "unhealthy_containers",
+            "Number of containers found unhealthy during the last check",
+            unhealthy_containers.clone(),
+        );
+
+        let restart_duration = Histogram::new(exponential_buckets(0.1, 2.0, 10));
+        registry.register(
+            "restart_duration_seconds",
+            "Duration of the restart call to Docker",
+            restart_duration.clone(),
+        );
+
+        let docker_api_errors = Family::<OperationLabels, Counter>::default();
+        registry.register(
+            "docker_api_errors",
+            "Number of failed calls to the Docker API",
+            docker_api_errors.clone(),
+        );
+
+        let webhook_deliveries = Family::<ResultLabels, Counter>::default();
+        registry.register(
+            "webhook_deliveries",
+            "Number of webhook deliveries",
+            webhook_deliveries.clone(),
+        );
+
+        let loop_lag = Gauge::<f64, AtomicU64>::default();
+        registry.register(
+            "loop_lag_seconds",
+            "How late the last check started compared to its schedule",
+            loop_lag.clone(),
+        );
+
+        let restarts_last_day = Family::<ContainerLabels, Gauge>::default();
+        registry.register(
+            "restarts_last_day",
+            "Number of restarts during the last 24 hours",
+            restarts_last_day.clone(),
+        );
+
+        let restarts_last_week = Family::<ContainerLabels, Gauge>::default();
+        registry.register(
+            "restarts_last_week",
+            "Number of restarts during the last 7 days",
+            restarts_last_week.clone(),
+        );
+
+        let mean_time_between_failures =
+            Family::<ContainerLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register(
+            "mean_time_between_failures_seconds",
+            "Mean time between a container turning unhealthy, over the last 7 days",
+            mean_time_between_failures.clone(),
+        );
+
+        let mean_time_to_recovery = Family::<ContainerLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register(
+            "mean_time_to_recovery_seconds",
+            "Mean time it took for a container to become healthy again, over the last 7 days",
+            mean_time_to_recovery.clone(),
+        );
+
+        Self {
+            registry,
+            restarts_attempted,
+            restarts_succeeded,
+            restarts_failed,
+            unhealthy_containers,
+            restart_duration,
+            docker_api_errors,
+            webhook_deliveries,
+            loop_lag,
+            restarts_last_day,
+            restarts_last_week,
+            mean_time_between_failures,
+            mean_time_to_recovery,
+            reliability_labels: Mutex::default(),
+        }
+    }
+
+    pub fn restart_attempted(&self, labels: &ContainerLabels) {
+        self.restarts_attempted.get_or_create(labels).inc();
+    }
+
+    pub fn restart_finished(&self, labels: &ContainerLabels, success: bool, duration: Duration) {
+        if success {
+            self.restarts_succeeded.get_or_create(labels).inc();
+        } else {
+            self.restarts_failed.get_or_create(labels).inc();
+        }
+
+        self.restart_duration.observe(duration.as_secs_f64());
+    }
+
+    pub fn set_unhealthy_containers(&self, count: usize) {
+        self.unhealthy_containers
+            .set(i64::try_from(count).unwrap_or(i64::MAX));
+    }
+
+    pub fn docker_api_error(&self, operation: &'static str) {
+        self.docker_api_errors
+            .get_or_create(&OperationLabels { operation })
+            .inc();
+    }
+
+    pub fn webhook_delivered(&self, success: bool) {
+        let result = if success { "success" } else { "failure" };
+
+        self.webhook_deliveries
+            .get_or_create(&ResultLabels { result })
+            .inc();
+    }
+
+    pub fn set_loop_lag(&self, lag: Duration) {
+        self.loop_lag.set(lag.as_secs_f64());
+    }
+
+    /// Updates the reliability statistics in place, and drops the containers without any history in the last week.
+    pub fn set_reliability<I: IntoIterator<Item = (ContainerLabels, ReliabilityStats)>>(
+        &self,
+        stats: I,
+    ) {
+        let mut current = HashSet::new();
+
+        for (labels, stats) in stats {
+            self.restarts_last_day
+                .get_or_create(&labels)
+                .set(i64::try_from(stats.restarts_24h).unwrap_or(i64::MAX));
+            self.restarts_last_week
+                .get_or_create(&labels)
+                .set(i64::try_from(stats.restarts_7d).unwrap_or(i64::MAX));
+
+            if let Some(mean_time_between_failures) = stats.mean_time_between_failures {
+                self.mean_time_between_failures
+                    .get_or_create(&labels)
+                    .set(mean_time_between_failures.as_secs_f64());
+            } else {
+                self.mean_time_between_failures.remove(&labels);
+            }
+
+            if let Some(mean_time_to_recovery) = stats.mean_time_to_recovery {
+                self.mean_time_to_recovery
+                    .get_or_create(&labels)
+                    .set(mean_time_to_recovery.as_secs_f64());
+            } else {
+                self.mean_time_to_recovery.remove(&labels);
+            }
+
+            current.insert(labels);
+        }
+
+        let mut reported = self
+            .reliability_labels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for labels in reported.difference(&current) {
+            self.restarts_last_day.remove(labels);
+            self.restarts_last_week.remove(labels);
+            self.mean_time_between_failures.remove(labels);
+            self.mean_time_to_recovery.remove(labels);
+        }
+
+        *reported = current;
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut buffer = String::new();
+
+        encode(&mut buffer, &self.registry)?;
+
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{deserializers, serializers};
+
+const DAY: Duration = Duration::from_hours(24);
+
+/// How far back we keep track of what happened.
+const WEEK: Duration = Duration::from_hours(7 * 24);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Sample {
+    /// Turned unhealthy.
+    Failure {
+        #[serde(
+            serialize_with = "serializers::timestamp",
+            deserialize_with = "deserializers::timestamp"
+        )]
+        at: SystemTime,
+    },
+    Restart {
+        #[serde(
+            serialize_with = "serializers::timestamp",
+            deserialize_with = "deserializers::timestamp"
+        )]
+        at: SystemTime,
+    },
+    /// Became healthy again, `took` after turning unhealthy.
+    Recovery {
+        #[serde(
+            serialize_with = "serializers::timestamp",
+            deserialize_with = "deserializers::timestamp"
+        )]
+        at: SystemTime,
+        #[serde(
+            serialize_with = "serializers::duration",
+            deserialize_with = "deserializers::duration"
+        )]
+        took: Duration,
+    },
+}
+
+impl Sample {
+    fn at(&self) -> SystemTime {
+        match *self {
+            Sample::Failure { at } | Sample::Restart { at } | Sample::Recovery { at, .. } => at,
+        }
+    }
+}
+
+/// What happened to a container during the last week.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct ContainerHistory {
+    compose_service: Option<Box<str>>,
+    samples: VecDeque<Sample>,
+    last_failure_reason: Option<Box<str>>,
+}
+
+impl ContainerHistory {
+    fn last_failure_at(&self) -> Option<SystemTime> {
+        self.samples.iter().rev().find_map(|sample| match *sample {
+            Sample::Failure { at } => Some(at),
+            Sample::Restart { .. } | Sample::Recovery { .. } => None,
+        })
+    }
+}
+
+/// Nobody cares about the nanoseconds of an average.
+fn round_to_millis(duration: Duration) -> Duration {
+    Duration::from_millis(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// Rolling statistics of a container, or of all containers of a compose service.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ReliabilityStats {
+    pub restarts_24h: usize,
+    pub restarts_7d: usize,
+    #[serde(serialize_with = "serializers::optional_duration")]
+    pub mean_time_between_failures: Option<Duration>,
+    #[serde(serialize_with = "serializers::optional_duration")]
+    pub mean_time_to_recovery: Option<Duration>,
+    #[serde(serialize_with = "serializers::optional_timestamp")]
+    pub last_failure_at: Option<SystemTime>,
+    pub last_failure_reason: Option<Box<str>>,
+}
+
+impl ReliabilityStats {
+    fn from_histories<'a, I: IntoIterator<Item = &'a ContainerHistory>>(
+        histories: I,
+        now: SystemTime,
+    ) -> Self {
+        let mut restarts_24h = 0;
+        let mut restarts_7d = 0;
+        let mut failures = Vec::new();
+        let mut recoveries = Vec::new();
+        let mut last_failure = None::<(SystemTime, Option<&str>)>;
+
+        for history in histories {
+            for sample in &history.samples {
+                match *sample {
+                    Sample::Failure { at } => failures.push(at),
+                    Sample::Restart { at } => {
+                        let age = now.duration_since(at).unwrap_or_default();
+
+                        restarts_24h += usize::from(age <= DAY);
+                        restarts_7d += usize::from(age <= WEEK);
+                    },
+                    Sample::Recovery { took, .. } => recoveries.push(took),
+                }
+            }
+
+            if let Some(at) = history.last_failure_at()
+                && last_failure.is_none_or(|(last_at, _)| last_at < at)
+            {
+                last_failure = Some((at, history.last_failure_reason.as_deref()));
+            }
+        }
+
+        failures.sort_unstable();
+
+        // the span between the first and the last failure, divided by the gaps in between
+        let mean_time_between_failures = match (failures.first(), failures.last()) {
+            (Some(first), Some(last)) if failures.len() > 1 => last
+                .duration_since(*first)
+                .ok()
+                .and_then(|span| span.checked_div(u32::try_from(failures.len() - 1).ok()?)),
+            _ => None,
+        };
+
+        let mean_time_to_recovery = u32::try_from(recoveries.len())
+            .ok()
+            .and_then(|count| recoveries.iter().sum::<Duration>().checked_div(count));
+
+        Self {
+            restarts_24h,
+            restarts_7d,
+            mean_time_between_failures: mean_time_between_failures.map(round_to_millis),
+            mean_time_to_recovery: mean_time_to_recovery.map(round_to_millis),
+            last_failure_at: last_failure.map(|(at, _)| at),
+            last_failure_reason: last_failure.and_then(|(_, reason)| reason.map(Into::into)),
+        }
+    }
+}
+
+/// Restart history per container, by name, as the id changes when a container is recreated.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Reliability {
+    containers: HashMap<Box<str>, ContainerHistory>,
+}
+
+impl Reliability {
+    fn history(&mut self, container_name: &str) -> &mut ContainerHistory {
+        self.containers.entry(container_name.into()).or_default()
+    }
+
+    pub fn failure(
+        &mut self,
+        container_name: &str,
+        compose_service: Option<&str>,
+        at: SystemTime,
+        reason: Option<&str>,
+    ) {
+        let history = self.history(container_name);
+
+        history.compose_service = compose_service.map(Into::into);
+        history.samples.push_back(Sample::Failure { at });
+        history.last_failure_reason = reason.map(Into::into);
+    }
+
+    pub fn restart(&mut self, container_name: &str, at: SystemTime) {
+        self.history(container_name)
+            .samples
+            .push_back(Sample::Restart { at });
+    }
+
+    pub fn recovery(&mut self, container_name: &str, at: SystemTime, took: Duration) {
+        self.history(container_name)
+            .samples
+            .push_back(Sample::Recovery { at, took });
+    }
+
+    /// Forgets what happened more than a week ago.
+    pub fn prune(&mut self, now: SystemTime) {
+        self.containers.retain(|_, history| {
+            history
+                .samples
+                .retain(|sample| now.duration_since(sample.at()).unwrap_or_default() <= WEEK);
+
+            !history.samples.is_empty()
+        });
+    }
+
+    /// Per container name, with its compose service, if any.
+    pub fn containers(
+        &self,
+        now: SystemTime,
+    ) -> impl Iterator<Item = (&str, Option<&str>, ReliabilityStats)> {
+        self.containers
+            .iter()
+            .map(move |(container_name, history)| {
+                (
+                    &**container_name,
+                    history.compose_service.as_deref(),
+                    ReliabilityStats::from_histories([history], now),
+                )
+            })
+    }
+
+    /// Per compose service, over all of its containers.
+    pub fn services(&self, now: SystemTime) -> HashMap<&str, ReliabilityStats> {
+        let mut services = HashMap::<&str, Vec<&ContainerHistory>>::new();
+
+        for history in self.containers.values() {
+            if let Some(ref service) = history.compose_service {
+                services.entry(&**service).or_default().push(history);
+            }
+        }
+
+        services
+            .into_iter()
+            .map(|(service, histories)| (service, ReliabilityStats::from_histories(histories, now)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::reliability::{Reliability, ReliabilityStats};
+
+    const HOUR: Duration = Duration::from_hours(1);
+
+    #[test]
+    fn summarizes_container() {
+        let now = SystemTime::now();
+
+        let mut reliability = Reliability::default();
+
+        for (hours_ago, reason) in [(50, "timeout"), (30, "timeout"), (10, "connection refused")] {
+            let at = now - HOUR * hours_ago;
+
+            reliability.failure("web", Some("web"), at, Some(reason));
+            reliability.restart("web", at + HOUR);
+            reliability.recovery("web", at + HOUR * 2, HOUR * 2);
+        }
+
+        let (_, compose_service, stats) = reliability.containers(now).next().unwrap();
+
+        assert_eq!(compose_service, Some("web"));
+        assert_eq!(
+            stats,
+            ReliabilityStats {
+                restarts_24h: 1,
+                restarts_7d: 3,
+                mean_time_between_failures: Some(HOUR * 20),
+                mean_time_to_recovery: Some(HOUR * 2),
+                last_failure_at: Some(now - HOUR * 10),
+                last_failure_reason: Some("connection refused".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn aggregates_services_and_prunes() {
+        let now = SystemTime::now();
+
+        let mut reliability = Reliability::default();
+
+        reliability.failure("web-1", Some("web"), now - HOUR * 24 * 8, Some("old"));
+        reliability.failure("web-1", Some("web"), now - HOUR * 3, Some("first"));
+        reliability.failure("web-2", Some("web"), now - HOUR, Some("second"));
+        reliability.failure("db", None, now - HOUR * 24 * 8, None);
+
+        reliability.prune(now);
+
+        let services = reliability.services(now);
+
+        assert_eq!(services.len(), 1);
+        assert_eq!(
+            services.get("web").unwrap(),
+            &ReliabilityStats {
+                restarts_24h: 0,
+                restarts_7d: 0,
+                mean_time_between_failures: Some(HOUR * 2),
+                mean_time_to_recovery: None,
+                last_failure_at: Some(now - HOUR),
+                last_failure_reason: Some("second".into()),
+            }
+        );
+        assert_eq!(
+            reliability.containers(now).count(),
+            2,
+            "`db` only failed more than a week ago"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre;
+use serde_json::Value as JsonValue;
+use tracing::{Level, event};
+
+use crate::config::AppConfig;
+use crate::docker_healer::{DockerHealer, HealerSettings};
+use crate::unhealthy_filters;
+use crate::webhook::WebHookNotifier;
+
+/// Settings, as named in the effective config, that are picked up without a restart.
+const RELOADABLE: [&str; 10] = [
+    "autoheal_container_label",
+    "autoheal_default_stop_timeout",
+    "autoheal_interval",
+    "autoheal_exclude_containers",
+    "autoheal_label_prefix",
+    "webhook_url",
+    "autoheal_notification_deduplication_window",
+    "autoheal_notification_digest_interval",
+    "autoheal_notification_rate_limit",
+    "autoheal_notify_lifecycle",
+];
+
+/// Puts back the current value of every changed setting that needs a restart, so the effective config stays truthful.
+///
+/// Returns the names of those settings.
+fn keep_restart_required(current: &JsonValue, new: &mut JsonValue) -> Vec<String> {
+    let (Some(current), Some(new)) = (current.as_object(), new.as_object_mut()) else {
+        return Vec::new();
+    };
+
+    let mut restart_required = Vec::new();
+
+    for (key, value) in new.iter_mut() {
+        let current_value = current.get(key).unwrap_or(&JsonValue::Null);
+
+        if !RELOADABLE.contains(&key.as_str()) && value != current_value {
+            value.clone_from(current_value);
+
+            restart_required.push(key.clone());
+        }
+    }
+
+    restart_required
+}
+
+/// The settings that differ between `current` and `new`, as `name: current -> new`.
+fn changes(current: &JsonValue, new: &JsonValue) -> Vec<String> {
+    let (Some(current), Some(new)) = (current.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+
+    new.iter()
+        .filter_map(|(key, value)| {
+            let current_value = current.get(key).unwrap_or(&JsonValue::Null);
+
+            (value != current_value).then(|| format!("{}: {} -> {}", key, current_value, value))
+        })
+        .collect()
+}
+
+/// Reads the configuration again, and swaps what can change into the running healer and notifier.
+///
+/// When the new configuration is invalid, the current one stays active.
+pub async fn reload(docker_healer: Arc<DockerHealer>, notifier: WebHookNotifier) {
+    // it reads the `_FILE` variables and the config file
+    let config = match tokio::task::spawn_blocking(AppConfig::build)
+        .await
+        .map_err(eyre::Report::new)
+        .and_then(|result| result)
+    {
+        Ok(config) => config,
+        Err(error) => {
+            event!(
+                Level::ERROR,
+                ?error,
+                "Invalid configuration, keeping the current one"
+            );
+
+            return;
+        },
+    };
+
+    let mut effective_config = config.effective_config;
+
+    let current_config = docker_healer.settings().effective_config.clone();
+
+    let restart_required = keep_restart_required(&current_config, &mut effective_config);
+
+    if !restart_required.is_empty() {
+        event!(
+            Level::WARN,
+            ?restart_required,
+            "Some changed settings only take effect after a restart"
+        );
+    }
+
+    let changed = changes(&current_config, &effective_config);
+
+    notifier.reconfigure(config.notifier_config);
+
+    docker_healer.reconfigure(HealerSettings {
+        effective_config,
+        healer_config: config.healer_config,
+        filters: unhealthy_filters::build(config.container_label.as_deref()),
+    });
+
+    event!(Level::INFO, ?changed, "Reloaded configuration");
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Calls `on_change` whenever the modification time of `path` changes, checking every `interval`.
+pub async fn watch<F: Fn() -> R, R: Future<Output = ()>>(
+    path: &Path,
+    interval: Duration,
+    on_change: F,
+) -> ! {
+    let mut last_modified = modified(path).await;
+
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let modified = modified(path).await;
+
+        if modified != last_modified {
+            last_modified = modified;
+
+            // a file that's (temporarily) gone is not worth reporting as an invalid configuration
+            if modified.is_some() {
+                event!(Level::INFO, path = %path.display(), "Configuration file changed");
+
+                on_change().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::reload::{changes, keep_restart_required};
+
+    #[test]
+    fn keeps_settings_that_need_a_restart() {
+        let current = json!({
+            "autoheal_interval": "5s",
+            "autoheal_http_address": "0.0.0.0:9090",
+            "autoheal_audit_log": null,
+        });
+
+        let mut new = json!({
+            "autoheal_interval": "10s",
+            "autoheal_http_address": "0.0.0.0:8080",
+            "autoheal_audit_log": null,
+        });
+
+        let restart_required = keep_restart_required(&current, &mut new);
+
+        assert_eq!(restart_required, vec!["autoheal_http_address".to_owned()]);
+        assert_eq!(
+            new,
+            json!({
+                "autoheal_interval": "10s",
+                "autoheal_http_address": "0.0.0.0:9090",
+                "autoheal_audit_log": null,
+            })
+        );
+    }
+
+    #[test]
+    fn lists_changed_settings() {
+        let current = json!({
+            "autoheal_interval": "5s",
+            "webhook_url": null,
+        });
+
+        let new = json!({
+            "autoheal_interval": "10s",
+            "webhook_url": null,
+        });
+
+        assert_eq!(
+            changes(&current, &new),
+            vec![r#"autoheal_interval: "5s" -> "10s""#.to_owned()]
+        );
+    }
+}
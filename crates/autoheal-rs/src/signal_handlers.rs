@@ -12,14 +12,14 @@ use crate::shutdown::Shutdown;
     clippy::cast_possible_truncation,
     reason = "Waiting for `try_into()` to become const"
 )]
-const SIGINT: u8 = libc::SIGINT as u8;
+pub const SIGINT: u8 = libc::SIGINT as u8;
 
 #[expect(
     clippy::as_conversions,
     clippy::cast_possible_truncation,
     reason = "Waiting for `try_into()` to become const"
 )]
-const SIGTERM: u8 = libc::SIGTERM as u8;
+pub const SIGTERM: u8 = libc::SIGTERM as u8;
 
 async fn register_sigterm_handler() -> Result<(), std::io::Error> {
     #[cfg(not(any(target_os = "windows", miri)))]
@@ -66,3 +66,52 @@ pub async fn wait_for_sigint() -> Shutdown {
         Shutdown::Signal(SIGINT)
     }
 }
+
+async fn handle_signal<F: Fn() -> R, R: Future<Output = ()>>(
+    #[cfg(not(any(target_os = "windows", miri)))] kind: SignalKind,
+    handler: F,
+) -> Result<(), std::io::Error> {
+    #[cfg(not(any(target_os = "windows", miri)))]
+    {
+        let mut signal = signal(kind)?;
+
+        while signal.recv().await.is_some() {
+            handler().await;
+        }
+    }
+
+    #[cfg(any(target_os = "windows", miri))]
+    let _r = std::future::pending::<F>().await;
+
+    Ok(())
+}
+
+/// Calls `handler` on every `SIGUSR1`, without stopping anything.
+pub async fn on_sigusr1<F: Fn()>(handler: F) {
+    if let Err(error) = handle_signal(
+        #[cfg(not(any(target_os = "windows", miri)))]
+        SignalKind::user_defined1(),
+        || {
+            handler();
+
+            std::future::ready(())
+        },
+    )
+    .await
+    {
+        event!(Level::ERROR, ?error, "Failed to register SIGUSR1 handler");
+    }
+}
+
+/// Calls `handler` on every `SIGHUP`, without stopping anything.
+pub async fn on_sighup<F: Fn() -> R, R: Future<Output = ()>>(handler: F) {
+    if let Err(error) = handle_signal(
+        #[cfg(not(any(target_os = "windows", miri)))]
+        SignalKind::hangup(),
+        handler,
+    )
+    .await
+    {
+        event!(Level::ERROR, ?error, "Failed to register SIGHUP handler");
+    }
+}
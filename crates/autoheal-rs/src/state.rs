@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use hashbrown::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::{Level, event};
+
+use crate::reliability::Reliability;
+use crate::utils::{deserializers, serializers};
+
+/// How many [`HealEvent`]s we keep around.
+const MAX_EVENTS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Restarted,
+    RestartFailed,
+    Skipped,
+    Recovered,
+}
+
+/// Something we did (or decided not to do) to a container.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HealEvent {
+    #[serde(
+        serialize_with = "serializers::timestamp",
+        deserialize_with = "deserializers::timestamp"
+    )]
+    pub at: SystemTime,
+    pub container_id: Box<str>,
+    pub container_name: Option<Box<str>>,
+    pub kind: EventKind,
+    pub reason: Option<Box<str>>,
+}
+
+/// A container we found to be unhealthy during the last check.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackedContainer {
+    pub id: Box<str>,
+    pub name: Option<Box<str>>,
+    pub times_unhealthy: usize,
+    #[serde(
+        serialize_with = "serializers::timestamp",
+        deserialize_with = "deserializers::timestamp"
+    )]
+    pub unhealthy_since: SystemTime,
+    pub excluded: bool,
+    pub last_action: Option<EventKind>,
+    #[serde(
+        serialize_with = "serializers::optional_timestamp",
+        deserialize_with = "deserializers::optional_timestamp"
+    )]
+    pub last_action_at: Option<SystemTime>,
+}
+
+impl TrackedContainer {
+    pub fn new(id: Box<str>, name: Option<Box<str>>) -> Self {
+        Self {
+            id,
+            name,
+            times_unhealthy: 0,
+            unhealthy_since: SystemTime::now(),
+            excluded: false,
+            last_action: None,
+            last_action_at: None,
+        }
+    }
+}
+
+/// Healing is paused until `until`, or until resumed when there is none.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Pause {
+    #[serde(
+        serialize_with = "serializers::optional_timestamp",
+        deserialize_with = "deserializers::optional_timestamp"
+    )]
+    pub until: Option<SystemTime>,
+}
+
+impl Pause {
+    fn is_active(&self, now: SystemTime) -> bool {
+        self.until.is_none_or(|until| now < until)
+    }
+}
+
+/// Changes to the configured behavior, made through the control API.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Overrides {
+    pub paused: Option<Pause>,
+    /// Keyed by container name.
+    pub paused_containers: HashMap<Box<str>, Pause>,
+    /// On top of the configured exclusions.
+    pub excluded_containers: HashSet<Box<str>>,
+}
+
+/// How the last iteration of the monitor loop went.
+#[derive(Clone, Copy, Debug)]
+pub struct LastCheck {
+    pub at: Instant,
+    pub docker_reachable: bool,
+}
+
+/// Running totals since startup.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counters {
+    pub checks: u64,
+    pub failed_checks: u64,
+    pub restarts: u64,
+    pub failed_restarts: u64,
+    pub skips: u64,
+    pub recoveries: u64,
+}
+
+/// What the healer knows, shared with the API.
+#[derive(Default)]
+pub struct HealerState {
+    /// Keyed by container id.
+    pub containers: HashMap<Box<str>, TrackedContainer>,
+    pub events: VecDeque<HealEvent>,
+    pub next_check_at: Option<SystemTime>,
+    pub last_check: Option<LastCheck>,
+    pub overrides: Overrides,
+    pub reliability: Reliability,
+    pub counters: Counters,
+}
+
+impl HealerState {
+    pub fn record(&mut self, event: HealEvent) {
+        let counter = match event.kind {
+            EventKind::Restarted => &mut self.counters.restarts,
+            EventKind::RestartFailed => &mut self.counters.failed_restarts,
+            EventKind::Skipped => &mut self.counters.skips,
+            EventKind::Recovered => &mut self.counters.recoveries,
+        };
+
+        *counter += 1;
+
+        if let (EventKind::Restarted, Some(container_name)) =
+            (event.kind, event.container_name.as_deref())
+        {
+            self.reliability.restart(container_name, event.at);
+        }
+
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
+    }
+
+    pub fn finish_check(&mut self, docker_reachable: bool) {
+        self.last_check = Some(LastCheck {
+            at: Instant::now(),
+            docker_reachable,
+        });
+
+        self.counters.checks += 1;
+
+        if !docker_reachable {
+            self.counters.failed_checks += 1;
+        }
+    }
+
+    /// Forgets the pauses that ran out.
+    pub fn expire_pauses(&mut self, now: SystemTime) {
+        if self
+            .overrides
+            .paused
+            .is_some_and(|pause| !pause.is_active(now))
+        {
+            event!(Level::INFO, "Pause expired, resuming healing");
+
+            self.overrides.paused = None;
+        }
+
+        self.overrides
+            .paused_containers
+            .retain(|container_name, pause| {
+                let active = pause.is_active(now);
+
+                if !active {
+                    event!(
+                        Level::INFO,
+                        %container_name,
+                        "Pause expired, resuming healing of container"
+                    );
+                }
+
+                active
+            });
+    }
+
+    /// Why we shouldn't touch a container with these names right now, if at all.
+    pub fn pause_reason(&self, names: &[Box<str>], now: SystemTime) -> Option<&'static str> {
+        if self
+            .overrides
+            .paused
+            .is_some_and(|pause| pause.is_active(now))
+        {
+            Some("Healing is paused")
+        } else if names.iter().any(|name| {
+            self.overrides
+                .paused_containers
+                .get(name)
+                .is_some_and(|pause| pause.is_active(now))
+        }) {
+            Some("Healing is paused for this container")
+        } else {
+            None
+        }
+    }
+
+    pub fn is_excluded(&self, names: &[Box<str>]) -> bool {
+        names
+            .iter()
+            .any(|name| self.overrides.excluded_containers.contains(name))
+    }
+
+    /// When we'd restart this container at the earliest, if it stays unhealthy. There is no backoff, so that's the next check, unless paused.
+    pub fn next_eligible_restart_at(&self, container: &TrackedContainer) -> Option<SystemTime> {
+        if container.excluded {
+            return None;
+        }
+
+        let now = SystemTime::now();
+
+        let container_pause = container
+            .name
+            .as_ref()
+            .and_then(|name| self.overrides.paused_containers.get(name));
+
+        let mut at = self.next_check_at?;
+
+        for pause in [self.overrides.paused.as_ref(), container_pause]
+            .into_iter()
+            .flatten()
+            .filter(|pause| pause.is_active(now))
+        {
+            // paused until resumed, we can't tell
+            at = at.max(pause.until?);
+        }
+
+        Some(at)
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use hashbrown::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice as from_json_slice, to_vec as to_json_vec};
+
+use crate::reliability::Reliability;
+use crate::state::{HealEvent, HealerState, Overrides, TrackedContainer};
+
+/// The part of [`HealerState`] worth keeping across restarts, the rest is rebuilt by the next check.
+#[derive(Serialize)]
+struct PersistedStateRef<'a> {
+    containers: &'a HashMap<Box<str>, TrackedContainer>,
+    events: &'a VecDeque<HealEvent>,
+    overrides: &'a Overrides,
+    reliability: &'a Reliability,
+}
+
+#[derive(Deserialize)]
+pub struct PersistedState {
+    pub containers: HashMap<Box<str>, TrackedContainer>,
+    pub events: VecDeque<HealEvent>,
+    pub overrides: Overrides,
+    /// Not there in state files written before we kept track of it.
+    #[serde(default)]
+    pub reliability: Reliability,
+}
+
+impl PersistedState {
+    /// Forgets the containers that don't exist anymore, so they don't show up as recovered.
+    pub fn reconcile(&mut self, existing: &HashSet<Box<str>>) -> usize {
+        let before = self.containers.len();
+
+        self.containers.retain(|id, _| existing.contains(id));
+
+        before - self.containers.len()
+    }
+
+    pub fn restore_into(self, state: &mut HealerState) {
+        state.containers = self.containers;
+        state.events = self.events;
+        state.overrides = self.overrides;
+        state.reliability = self.reliability;
+    }
+}
+
+pub fn encode(state: &HealerState) -> Result<Vec<u8>, serde_json::Error> {
+    to_json_vec(&PersistedStateRef {
+        containers: &state.containers,
+        events: &state.events,
+        overrides: &state.overrides,
+        reliability: &state.reliability,
+    })
+}
+
+#[derive(Clone)]
+pub struct StateFile {
+    path: PathBuf,
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    PathBuf::from(temporary)
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `None` when there is no state file yet.
+    pub fn load(&self) -> Result<Option<PersistedState>, eyre::Report> {
+        let contents = match std::fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(eyre::Report::new(error)
+                    .wrap_err(format!("Failed to read `{}`", self.path.display())));
+            },
+        };
+
+        let state = from_json_slice(&contents).map_err(|error| {
+            eyre::Report::new(error).wrap_err(format!("Failed to parse `{}`", self.path.display()))
+        })?;
+
+        Ok(Some(state))
+    }
+
+    /// Writes to a temporary file next to the state file first, and moves it over, so we never leave a half-written file behind.
+    pub fn save(&self, contents: &[u8]) -> Result<(), eyre::Report> {
+        let temporary = temporary_path(&self.path);
+
+        let write = || -> Result<(), std::io::Error> {
+            let mut file = File::create(&temporary)?;
+
+            file.write_all(contents)?;
+            file.sync_all()?;
+
+            std::fs::rename(&temporary, &self.path)
+        };
+
+        write().map_err(|error| {
+            eyre::Report::new(error).wrap_err(format!("Failed to write `{}`", self.path.display()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashSet;
+    use pretty_assertions::assert_eq;
+
+    use crate::state::{HealerState, Pause, TrackedContainer};
+    use crate::state_file::{StateFile, encode};
+    use crate::utils::test_directory::TestDirectory;
+
+    fn tracked(id: &str, times_unhealthy: usize) -> TrackedContainer {
+        TrackedContainer {
+            times_unhealthy,
+            ..TrackedContainer::new(id.into(), Some(id.into()))
+        }
+    }
+
+    #[test]
+    fn missing_file_is_no_state() {
+        let directory = TestDirectory::new("state-file-missing");
+        let state_file = StateFile::new(directory.join("state.json"));
+
+        assert!(
+            state_file.load().unwrap().is_none(),
+            "Nothing to load before the first save"
+        );
+    }
+
+    #[test]
+    fn round_trips_and_reconciles() {
+        let directory = TestDirectory::new("state-file-round-trip");
+        let state_file = StateFile::new(directory.join("state.json"));
+
+        let mut state = HealerState::default();
+        state.containers.insert("gone".into(), tracked("gone", 1));
+        state
+            .containers
+            .insert("still-here".into(), tracked("still-here", 3));
+        state
+            .overrides
+            .paused_containers
+            .insert("web".into(), Pause { until: None });
+
+        state_file.save(&encode(&state).unwrap()).unwrap();
+
+        let mut persisted = state_file.load().unwrap().unwrap();
+
+        let removed = persisted.reconcile(&HashSet::from_iter(["still-here".into()]));
+
+        let mut restored = HealerState::default();
+        persisted.restore_into(&mut restored);
+
+        assert_eq!(removed, 1);
+        assert_eq!(
+            restored
+                .containers
+                .get("still-here")
+                .map(|container| container.times_unhealthy),
+            Some(3)
+        );
+        assert!(
+            !restored.containers.contains_key("gone"),
+            "Containers that are gone are forgotten"
+        );
+        assert!(
+            restored.overrides.paused_containers.contains_key("web"),
+            "Pauses survive"
+        );
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        let directory = TestDirectory::new("state-file-garbage");
+        let path = directory.join("state.json");
+
+        std::fs::write(&path, "{").unwrap();
+
+        assert!(
+            StateFile::new(path).load().is_err(),
+            "A broken state file is reported"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::from_slice as from_json_slice;
+use tracing::{Level, event};
+
+use crate::healthcheck;
+use crate::shutdown::Shutdown;
+use crate::state::{Overrides, TrackedContainer};
+use crate::utils::{deserializers, table};
+
+/// An entry of `/containers`.
+#[derive(Deserialize)]
+struct ContainerStatus {
+    #[serde(flatten)]
+    container: TrackedContainer,
+    #[serde(deserialize_with = "deserializers::optional_timestamp")]
+    next_eligible_restart_at: Option<SystemTime>,
+}
+
+async fn get_json<T: DeserializeOwned>(address: SocketAddr, path: &str) -> Result<T, eyre::Report> {
+    let (status, body) = healthcheck::get(address, path).await?;
+
+    if !status.is_success() {
+        return Err(eyre::Report::msg(format!(
+            "{} returned {}: {}",
+            path,
+            status,
+            String::from_utf8_lossy(&body)
+        )));
+    }
+
+    from_json_slice(&body).map_err(|error| {
+        eyre::Report::new(error).wrap_err(format!("Failed to parse the response of {}", path))
+    })
+}
+
+fn timestamp(at: Option<SystemTime>) -> String {
+    at.map_or_else(
+        || "-".to_owned(),
+        |at| humantime::format_rfc3339_seconds(at).to_string(),
+    )
+}
+
+fn print_overrides(overrides: &Overrides) {
+    match overrides.paused {
+        Some(pause) => println!("Healing: paused until {}", timestamp(pause.until)),
+        None => println!("Healing: active"),
+    }
+
+    let mut paused_containers = overrides.paused_containers.iter().collect::<Vec<_>>();
+    paused_containers.sort_unstable_by_key(|&(container_name, _)| container_name);
+
+    for (container_name, pause) in paused_containers {
+        println!(
+            "Paused container: {} until {}",
+            container_name,
+            timestamp(pause.until)
+        );
+    }
+
+    let mut excluded_containers = overrides.excluded_containers.iter().collect::<Vec<_>>();
+    excluded_containers.sort_unstable();
+
+    for container_name in excluded_containers {
+        println!("Excluded through the API: {}", container_name);
+    }
+}
+
+fn print_containers(containers: &[ContainerStatus]) {
+    if containers.is_empty() {
+        println!("No unhealthy containers");
+
+        return;
+    }
+
+    let now = SystemTime::now();
+
+    let rows = containers
+        .iter()
+        .map(|status| {
+            let container = &status.container;
+
+            let unhealthy_for = now
+                .duration_since(container.unhealthy_since)
+                .unwrap_or_default();
+
+            vec![
+                container
+                    .name
+                    .as_deref()
+                    .unwrap_or("<UNNAMED CONTAINER>")
+                    .to_owned(),
+                container.id.chars().take(12).collect(),
+                humantime::format_duration(Duration::from_secs(unhealthy_for.as_secs()))
+                    .to_string(),
+                container.times_unhealthy.to_string(),
+                container.excluded.to_string(),
+                container
+                    .last_action
+                    .map_or_else(|| "-".to_owned(), |kind| format!("{:?}", kind)),
+                timestamp(status.next_eligible_restart_at),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print!(
+        "{}",
+        table::render(
+            &[
+                "NAME",
+                "ID",
+                "UNHEALTHY FOR",
+                "TIMES",
+                "EXCLUDED",
+                "LAST ACTION",
+                "NEXT RESTART",
+            ],
+            &rows,
+        )
+    );
+}
+
+/// Asks the running instance what it's doing, through the same address as the healthcheck.
+pub async fn run(address: Option<SocketAddr>) -> Shutdown {
+    let Some(address) = address else {
+        return Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "The status needs `AUTOHEAL_HTTP_ADDRESS` to reach the running instance",
+        };
+    };
+
+    let result = async {
+        let overrides = get_json::<Overrides>(address, "/overrides").await?;
+        let containers = get_json::<Vec<ContainerStatus>>(address, "/containers").await?;
+
+        Ok::<_, eyre::Report>((overrides, containers))
+    }
+    .await;
+
+    let (overrides, containers) = match result {
+        Ok(status) => status,
+        Err(error) => {
+            event!(Level::ERROR, ?error, "Failed to reach the running instance");
+
+            return Shutdown::OperationalFailure {
+                code: ExitCode::FAILURE,
+                message: "Failed to reach the running instance",
+            };
+        },
+    };
+
+    let liveness = healthcheck::check(address).await;
+
+    match liveness {
+        Ok(()) => println!("Instance at {}: healthy", address),
+        Err(ref error) => println!("Instance at {}: unhealthy, {}", address, error),
+    }
+
+    print_overrides(&overrides);
+    print_containers(&containers);
+
+    match liveness {
+        Ok(()) => Shutdown::Success,
+        Err(_) => Shutdown::OperationalFailure {
+            code: ExitCode::FAILURE,
+            message: "Unhealthy",
+        },
+    }
+}
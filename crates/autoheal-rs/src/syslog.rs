@@ -0,0 +1,512 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs as _, UdpSocket};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::net::UnixDatagram;
+#[cfg(not(target_os = "windows"))]
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+use crate::utils::read_env;
+
+const APP_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Private enterprise number reserved for documentation, see RFC 5612.
+const ENTERPRISE_NUMBER: u32 = 32473;
+
+const NIL_VALUE: &str = "-";
+
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lines waiting for the writer, beyond this they're dropped rather than holding up whoever logs.
+const QUEUE_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+impl FromStr for Facility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let facility = match &*s.to_ascii_lowercase() {
+            "kern" => Facility::Kern,
+            "user" => Facility::User,
+            "mail" => Facility::Mail,
+            "daemon" => Facility::Daemon,
+            "auth" => Facility::Auth,
+            "syslog" => Facility::Syslog,
+            "lpr" => Facility::Lpr,
+            "news" => Facility::News,
+            "uucp" => Facility::Uucp,
+            "cron" => Facility::Cron,
+            "authpriv" => Facility::AuthPriv,
+            "ftp" => Facility::Ftp,
+            "local0" => Facility::Local0,
+            "local1" => Facility::Local1,
+            "local2" => Facility::Local2,
+            "local3" => Facility::Local3,
+            "local4" => Facility::Local4,
+            "local5" => Facility::Local5,
+            "local6" => Facility::Local6,
+            "local7" => Facility::Local7,
+            _ => return Err(format!("Unknown syslog facility `{}`", s)),
+        };
+
+        Ok(facility)
+    }
+}
+
+fn to_severity(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    #[cfg(not(target_os = "windows"))]
+    Unix(PathBuf),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn resolve(address: &str) -> Result<SocketAddr, String> {
+            address
+                .to_socket_addrs()
+                .map_err(|error| format!("Failed to resolve `{}`: {}", address, error))?
+                .next()
+                .ok_or_else(|| format!("`{}` did not resolve to any address", address))
+        }
+
+        if let Some(address) = s.strip_prefix("udp://") {
+            Ok(Target::Udp(resolve(address)?))
+        } else if let Some(address) = s.strip_prefix("tcp://") {
+            Ok(Target::Tcp(resolve(address)?))
+        } else if let Some(path) = s.strip_prefix("unix://") {
+            #[cfg(target_os = "windows")]
+            {
+                Err(format!(
+                    "Unix sockets are not supported on Windows. You tried to connect to \"{}\"",
+                    path
+                ))
+            }
+
+            #[cfg(not(target_os = "windows"))]
+            {
+                Ok(Target::Unix(PathBuf::from(path)))
+            }
+        } else {
+            Err(format!(
+                "Syslog address `{}` must start with `udp://`, `tcp://` or `unix://`",
+                s
+            ))
+        }
+    }
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    Tcp {
+        address: SocketAddr,
+        stream: Option<TcpStream>,
+    },
+    #[cfg(not(target_os = "windows"))]
+    Unix {
+        path: PathBuf,
+        socket: UnixDatagram,
+    },
+}
+
+impl Transport {
+    fn connect(target: Target) -> Result<Transport, std::io::Error> {
+        match target {
+            Target::Udp(address) => {
+                let bind_address = if address.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+
+                let socket = UdpSocket::bind(bind_address)?;
+                socket.connect(address)?;
+
+                Ok(Transport::Udp(socket))
+            },
+            Target::Tcp(address) => Ok(Transport::Tcp {
+                address,
+                stream: None,
+            }),
+            #[cfg(not(target_os = "windows"))]
+            Target::Unix(path) => Ok(Transport::Unix {
+                path,
+                socket: UnixDatagram::unbound()?,
+            }),
+        }
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), std::io::Error> {
+        match *self {
+            Transport::Udp(ref socket) => socket.send(line.as_bytes()).map(|_| ()),
+            Transport::Tcp {
+                address,
+                ref mut stream,
+            } => {
+                let connected = if let Some(connected) = stream.as_mut() {
+                    connected
+                } else {
+                    let connected = TcpStream::connect_timeout(&address, SOCKET_TIMEOUT)?;
+                    connected.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+
+                    stream.insert(connected)
+                };
+
+                // octet-counting framing, see RFC 6587, section 3.4.1
+                let result = connected
+                    .write_all(format!("{} {}", line.len(), line).as_bytes())
+                    .and_then(|()| connected.flush());
+
+                if result.is_err() {
+                    // reconnect on the next message
+                    *stream = None;
+                }
+
+                result
+            },
+            #[cfg(not(target_os = "windows"))]
+            Transport::Unix {
+                ref path,
+                ref socket,
+            } => socket.send_to(line.as_bytes(), path).map(|_| ()),
+        }
+    }
+}
+
+/// Fields of a `tracing` event, split into what goes into the structured data and what goes into the message.
+#[derive(Default)]
+struct SyslogFields {
+    container_id: Option<String>,
+    container_name: Option<String>,
+    message: String,
+    rest: String,
+}
+
+impl SyslogFields {
+    fn record(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.message = value,
+            "container_id" | "container_short_id" => {
+                // prefer the full id when we have both
+                if field.name() == "container_id" || self.container_id.is_none() {
+                    self.container_id = Some(value);
+                }
+            },
+            "container_name" => self.container_name = Some(value),
+            name => {
+                let _r = write!(self.rest, " {}={}", name, value);
+            },
+        }
+    }
+}
+
+impl Visit for SyslogFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+/// Escapes a `PARAM-VALUE` as required by RFC 5424, section 6.3.3.
+fn escape_param_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn format_structured_data(fields: &SyslogFields) -> String {
+    if fields.container_id.is_none() && fields.container_name.is_none() {
+        return NIL_VALUE.to_owned();
+    }
+
+    let mut structured_data = format!("[container@{}", ENTERPRISE_NUMBER);
+
+    if let Some(ref container_id) = fields.container_id {
+        let _r = write!(
+            structured_data,
+            " id=\"{}\"",
+            escape_param_value(container_id)
+        );
+    }
+
+    if let Some(ref container_name) = fields.container_name {
+        let _r = write!(
+            structured_data,
+            " name=\"{}\"",
+            escape_param_value(container_name)
+        );
+    }
+
+    structured_data.push(']');
+
+    structured_data
+}
+
+fn format_line(
+    facility: Facility,
+    level: Level,
+    timestamp: SystemTime,
+    hostname: &str,
+    process_id: u32,
+    fields: &SyslogFields,
+) -> String {
+    #[expect(clippy::as_conversions, reason = "Enum discriminant")]
+    let priority = (facility as u8) * 8 + to_severity(level);
+
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}{}",
+        priority,
+        humantime::format_rfc3339_micros(timestamp),
+        hostname,
+        APP_NAME,
+        process_id,
+        NIL_VALUE,
+        format_structured_data(fields),
+        fields.message,
+        fields.rest,
+    )
+}
+
+fn read_hostname() -> Box<str> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|hostname| hostname.trim().to_owned())
+        .filter(|hostname| !hostname.is_empty())
+        .map_or_else(|| NIL_VALUE.into(), String::into_boxed_str)
+}
+
+/// Sends every `tracing` event as an RFC 5424 syslog message.
+///
+/// The socket is written to from a thread of its own, as connecting and writing block.
+pub struct SyslogLayer {
+    facility: Facility,
+    hostname: Box<str>,
+    process_id: u32,
+    sender: SyncSender<String>,
+}
+
+fn write_lines(mut transport: Transport, receiver: &Receiver<String>) {
+    let mut unreachable = false;
+
+    for line in receiver {
+        match transport.send(&line) {
+            Ok(()) if unreachable => {
+                unreachable = false;
+
+                eprintln!("Syslog is reachable again");
+            },
+            // once per outage, as it'd be every message otherwise
+            Err(error) if !unreachable => {
+                unreachable = true;
+
+                // we cannot use `tracing` here, as that would end up back in this layer
+                eprintln!(
+                    "Failed to send message to syslog, dropping messages until it's reachable again: {}",
+                    error
+                );
+            },
+            Ok(()) | Err(_) => {},
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = SyslogFields::default();
+        event.record(&mut fields);
+
+        let line = format_line(
+            self.facility,
+            *event.metadata().level(),
+            SystemTime::now(),
+            &self.hostname,
+            self.process_id,
+            &fields,
+        );
+
+        // full means the writer can't keep up, or syslog is down, either way the line is lost
+        let _r = self.sender.try_send(line);
+    }
+}
+
+/// Builds the syslog layer from `SYSLOG_ADDRESS`, `SYSLOG_FACILITY`, `SYSLOG_HOSTNAME` and `SYSLOG_LEVEL`.
+///
+/// Returns `None` when `SYSLOG_ADDRESS` is not set.
+pub fn build_from_env() -> Result<Option<(SyslogLayer, LevelFilter)>, eyre::Report> {
+    let Some(address) = read_env("SYSLOG_ADDRESS")? else {
+        return Ok(None);
+    };
+
+    let target = Target::from_str(&address).map_err(eyre::Report::msg)?;
+
+    let facility = read_env("SYSLOG_FACILITY")?
+        .map(|facility| Facility::from_str(&facility))
+        .transpose()
+        .map_err(eyre::Report::msg)?
+        .unwrap_or(Facility::Daemon);
+
+    let level = read_env("SYSLOG_LEVEL")?
+        .map(|level| LevelFilter::from_str(&level))
+        .transpose()?
+        .unwrap_or(LevelFilter::INFO);
+
+    let hostname = read_env("SYSLOG_HOSTNAME")?.map_or_else(read_hostname, String::into_boxed_str);
+
+    let transport = Transport::connect(target)
+        .map_err(|error| eyre::Report::new(error).wrap_err("Failed to set up syslog socket"))?;
+
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+
+    thread::Builder::new()
+        .name("syslog".to_owned())
+        .spawn(move || write_lines(transport, &receiver))
+        .map_err(|error| eyre::Report::new(error).wrap_err("Failed to start syslog writer"))?;
+
+    let layer = SyslogLayer {
+        facility,
+        hostname,
+        process_id: std::process::id(),
+        sender,
+    };
+
+    Ok(Some((layer, level)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+    use tracing::Level;
+
+    use crate::syslog::{Facility, SyslogFields, Target, escape_param_value, format_line};
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(
+            Target::from_str("udp://127.0.0.1:514"),
+            Ok(Target::Udp("127.0.0.1:514".parse().unwrap()))
+        );
+        assert_eq!(
+            Target::from_str("tcp://127.0.0.1:601"),
+            Ok(Target::Tcp("127.0.0.1:601".parse().unwrap()))
+        );
+        assert_eq!(
+            Target::from_str("unix:///dev/log"),
+            Ok(Target::Unix("/dev/log".into()))
+        );
+        assert!(
+            Target::from_str("/dev/log").is_err(),
+            "Addresses need a scheme"
+        );
+    }
+
+    #[test]
+    fn escape_values() {
+        assert_eq!(escape_param_value(r#"a"b\c]d"#), r#"a\"b\\c\]d"#);
+    }
+
+    #[test]
+    fn format_heal_event() {
+        let fields = SyslogFields {
+            container_id: Some("0123456789ab".into()),
+            container_name: Some("web".into()),
+            message: "Restarting container failed.".into(),
+            rest: " error=Timeout".into(),
+        };
+
+        let line = format_line(
+            Facility::Daemon,
+            Level::WARN,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            "host",
+            42,
+            &fields,
+        );
+
+        assert_eq!(
+            line,
+            "<28>1 2023-11-14T22:13:20.000000Z host autoheal-rs 42 - [container@32473 id=\"0123456789ab\" name=\"web\"] Restarting container failed. error=Timeout"
+        );
+    }
+
+    #[test]
+    fn format_without_container() {
+        let fields = SyslogFields {
+            message: "Daemon".into(),
+            ..SyslogFields::default()
+        };
+
+        let line = format_line(
+            Facility::Local0,
+            Level::INFO,
+            SystemTime::UNIX_EPOCH,
+            "-",
+            1,
+            &fields,
+        );
+
+        assert_eq!(
+            line,
+            "<134>1 1970-01-01T00:00:00.000000Z - autoheal-rs 1 - - Daemon"
+        );
+    }
+}
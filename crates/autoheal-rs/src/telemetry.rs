@@ -0,0 +1,63 @@
+use color_eyre::eyre;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{Level, Subscriber, event};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::utils::read_env;
+
+const APP_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Builds a tracer provider that exports spans over OTLP, configured through the standard `OTEL_*` variables.
+///
+/// Returns `None` when neither `OTEL_EXPORTER_OTLP_ENDPOINT` nor `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set.
+///
+/// The gRPC transport needs to be built inside of a Tokio runtime.
+pub fn build_from_env() -> Result<Option<SdkTracerProvider>, eyre::Report> {
+    if read_env("OTEL_EXPORTER_OTLP_ENDPOINT")?.is_none()
+        && read_env("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")?.is_none()
+    {
+        return Ok(None);
+    }
+
+    // picks gRPC or HTTP based on `OTEL_EXPORTER_OTLP_PROTOCOL`
+    let exporter = SpanExporter::builder().build()?;
+
+    let resource = if read_env("OTEL_SERVICE_NAME")?.is_some() {
+        Resource::builder().build()
+    } else {
+        Resource::builder().with_service_name(APP_NAME).build()
+    };
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+
+    Ok(Some(provider))
+}
+
+/// Only our own spans, as exporting those of the HTTP clients we export with would never end.
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S> + use<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(APP_NAME))
+        .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE))
+}
+
+/// Flushes what's still buffered.
+pub fn shutdown(provider: &SdkTracerProvider) {
+    if let Err(error) = provider.shutdown() {
+        event!(
+            Level::WARN,
+            ?error,
+            "Failed to shut down the tracer provider"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use color_eyre::eyre;
+use tracing::{Level, event};
+use twistlock::filters::Filters;
+
+use crate::labels::LabelKeys;
+
+const VARIABLE_PREFIX: &str = "AUTOHEAL_";
+
+fn closest<'c, I: IntoIterator<Item = &'c str>>(name: &str, candidates: I) -> Option<&'c str> {
+    candidates
+        .into_iter()
+        .min_by_key(|candidate| strsim::levenshtein(name, candidate))
+}
+
+/// `AUTOHEAL_*` names that aren't in `known`, each with the closest one that is.
+fn unknown_variables<I: IntoIterator<Item = String>>(
+    names: I,
+    known: &[String],
+) -> Vec<(String, Option<&str>)> {
+    let mut unknown = names
+        .into_iter()
+        .filter(|name| name.starts_with(VARIABLE_PREFIX) && !known.contains(name))
+        .map(|name| {
+            let suggestion = closest(&name, known.iter().map(String::as_str));
+
+            (name, suggestion)
+        })
+        .collect::<Vec<_>>();
+
+    unknown.sort_unstable();
+
+    unknown
+}
+
+/// Warns about `AUTOHEAL_*` variables that aren't settings, as they're most likely typos, and fails on them when `strict`.
+pub fn check_environment(command: &clap::Command, strict: bool) -> Result<(), eyre::Report> {
+    let known = command
+        .get_arguments()
+        .filter_map(clap::Arg::get_env)
+        .flat_map(|env| {
+            let env = env.to_string_lossy();
+
+            [env.to_string(), format!("{}_FILE", env)]
+        })
+        .collect::<Vec<_>>();
+
+    let unknown = unknown_variables(
+        std::env::vars_os().map(|(name, _)| name.to_string_lossy().into_owned()),
+        &known,
+    );
+
+    for &(ref variable, suggestion) in &unknown {
+        event!(
+            Level::WARN,
+            %variable,
+            did_you_mean = suggestion,
+            "Unknown environment variable"
+        );
+    }
+
+    if strict && !unknown.is_empty() {
+        return Err(eyre::Report::msg(format!(
+            "Unknown environment variables: {}",
+            unknown
+                .iter()
+                .map(|&(ref variable, _)| variable.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Labels under our prefix that we don't read, each with the closest one we do, leaving the one in `filters` alone.
+pub fn unknown_labels<'l, 'k, I: IntoIterator<Item = &'l str>>(
+    keys: I,
+    label_keys: &'k LabelKeys,
+    filters: &Filters,
+) -> Vec<(&'l str, &'k str)> {
+    let mut unknown = keys
+        .into_iter()
+        .filter(|&key| {
+            label_keys.is_ours(key)
+                && !label_keys.all().contains(&key)
+                && !filters
+                    .label
+                    .as_ref()
+                    .is_some_and(|label| label.contains_key(key))
+        })
+        .filter_map(|key| Some((key, closest(key, label_keys.all())?)))
+        .collect::<Vec<_>>();
+
+    unknown.sort_unstable();
+
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use pretty_assertions::assert_eq;
+
+    use crate::labels::LabelKeys;
+    use crate::typos::{unknown_labels, unknown_variables};
+    use crate::unhealthy_filters;
+
+    #[test]
+    fn suggests_closest_variable() {
+        let known = vec![
+            "AUTOHEAL_INTERVAL".to_owned(),
+            "AUTOHEAL_INTERVAL_FILE".to_owned(),
+            "AUTOHEAL_START_PERIOD".to_owned(),
+        ];
+
+        let unknown = unknown_variables(
+            [
+                "AUTOHEAL_INTERVALL".to_owned(),
+                "AUTOHEAL_START_PERIOD".to_owned(),
+                "PATH".to_owned(),
+            ],
+            &known,
+        );
+
+        assert_eq!(
+            unknown,
+            vec![("AUTOHEAL_INTERVALL".to_owned(), Some("AUTOHEAL_INTERVAL"))]
+        );
+    }
+
+    #[test]
+    fn suggests_closest_label() {
+        let labels = HashMap::<Box<str>, Box<str>>::from_iter([
+            ("autoheal.enable".into(), "true".into()),
+            ("autoheal.stop.timout".into(), "10".into()),
+            (
+                "autoheal.notify.url".into(),
+                "https://ntfy.sh/autoheal".into(),
+            ),
+            ("com.docker.compose.service".into(), "web".into()),
+        ]);
+
+        assert_eq!(
+            unknown_labels(
+                labels.keys().map(AsRef::as_ref),
+                &LabelKeys::new("autoheal"),
+                &unhealthy_filters::build(Some("autoheal.enable"))
+            ),
+            vec![("autoheal.stop.timout", "autoheal.stop.timeout")]
+        );
+    }
+}
@@ -1,8 +1,17 @@
+use std::env;
+use std::env::VarError;
+
+use color_eyre::eyre;
 use tokio::task::JoinHandle;
 
 use crate::shutdown::Shutdown;
 
+pub mod deserializers;
+pub mod serializers;
+pub mod table;
 pub mod task;
+#[cfg(test)]
+pub mod test_directory;
 
 pub async fn flatten_shutdown_handle(handle: JoinHandle<Shutdown>) -> Shutdown {
     match handle.await {
@@ -10,3 +19,14 @@ pub async fn flatten_shutdown_handle(handle: JoinHandle<Shutdown>) -> Shutdown {
         Err(join_error) => Shutdown::UnexpectedError(join_error.into()),
     }
 }
+
+/// Reads an environment variable, which is fine to be missing, but not to be garbage.
+pub fn read_env(name: &str) -> Result<Option<String>, eyre::Report> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(error @ VarError::NotUnicode(_)) => {
+            Err(eyre::Report::new(error).wrap_err(format!("Failed to read `{}`", name)))
+        },
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::{Duration, SystemTime};
+
+use serde::de::Error as _;
+use serde::{Deserialize as _, Deserializer};
+
+/// Counterpart of [`super::serializers::timestamp`].
+///
+/// Goes through an owned `String`, a borrowed `&str` can't hold escaped strings or anything buffered by `#[serde(flatten)]`.
+pub fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    humantime::parse_rfc3339_weak(&value).map_err(D::Error::custom)
+}
+
+/// Counterpart of [`super::serializers::optional_timestamp`].
+pub fn optional_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SystemTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| humantime::parse_rfc3339_weak(&value).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Counterpart of [`super::serializers::duration`].
+pub fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    humantime::parse_duration(&value).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+    use serde::{Deserialize, Serialize};
+    use serde_json::{from_str as from_json_str, to_string as to_json_string};
+
+    use crate::utils::{deserializers, serializers};
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+    struct WithTimestamps {
+        #[serde(
+            serialize_with = "serializers::timestamp",
+            deserialize_with = "deserializers::timestamp"
+        )]
+        at: SystemTime,
+        #[serde(
+            serialize_with = "serializers::optional_timestamp",
+            deserialize_with = "deserializers::optional_timestamp"
+        )]
+        until: Option<SystemTime>,
+    }
+
+    #[test]
+    fn round_trips_timestamps() {
+        let with_timestamps = WithTimestamps {
+            at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            until: None,
+        };
+
+        let json = to_json_string(&with_timestamps).unwrap();
+
+        assert_eq!(json, r#"{"at":"2023-11-14T22:13:20.123Z","until":null}"#);
+        assert_eq!(
+            from_json_str::<WithTimestamps>(&json).unwrap(),
+            with_timestamps
+        );
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    struct Flattened {
+        #[serde(flatten)]
+        inner: WithTimestamps,
+        name: Box<str>,
+    }
+
+    #[test]
+    fn parses_escaped_and_flattened_timestamps() {
+        let json = r#"{"at":"2023-11-14T22:13:20.123\u005a","until":"2023-11-14T22:13:20Z","name":"we\"b\\1"}"#;
+
+        assert_eq!(
+            from_json_str::<Flattened>(json).unwrap(),
+            Flattened {
+                inner: WithTimestamps {
+                    at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+                    until: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+                },
+                name: r#"we"b\1"#.into(),
+            }
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt::Write as _;
+
+/// Lines up `rows` under `headers`, for the commands meant to be read by people.
+pub fn render<S: AsRef<str>>(headers: &[&str], rows: &[Vec<S>]) -> String {
+    let mut widths = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect::<Vec<_>>();
+
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.as_ref().chars().count());
+        }
+    }
+
+    let mut rendered = String::new();
+
+    let mut render_row = |cells: &mut dyn Iterator<Item = &str>| {
+        let line = cells
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        let _r = writeln!(rendered, "{}", line.trim_end());
+    };
+
+    render_row(&mut headers.iter().copied());
+
+    for row in rows {
+        render_row(&mut row.iter().map(AsRef::as_ref));
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::utils::table::render;
+
+    #[test]
+    fn aligns_columns() {
+        let rendered = render(
+            &["NAME", "ACTION"],
+            &[vec!["web", "restart"], vec!["database", "none"]],
+        );
+
+        assert_eq!(
+            rendered,
+            "NAME      ACTION\nweb       restart\ndatabase  none\n"
+        );
+    }
+}
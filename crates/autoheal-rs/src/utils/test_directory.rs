@@ -0,0 +1,28 @@
+use std::path::{Path, PathBuf};
+
+/// A fresh directory for a test to write to, removed again once it's dropped.
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("autoheal-{}-{}", name, std::process::id()));
+
+        // left over from a run that didn't get to clean up
+        let _r = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _r = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,62 +1,60 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
 use color_eyre::eyre;
-use http::{Request, Response};
+use hashbrown::HashMap;
+use http::Request;
 use http_body_util::Full;
-use hyper::body::{Body, Bytes};
+use hyper::body::Bytes;
 use hyper::http::HeaderValue;
 use hyper::{Method, Uri};
-use hyper_rustls::HttpsConnectorBuilder;
-use hyper_util::client::legacy::Client;
-use hyper_util::client::legacy::connect::Connect;
-use hyper_util::rt::TokioExecutor;
-use tracing::{Level, event};
-
-/// Executes a request on a client.
-///
-/// # Errors
-///
-/// When the request errors.
-pub async fn execute_request<C, B>(
-    client: &Client<C, B>,
-    request: Request<B>,
-) -> Result<Response<hyper::body::Incoming>, hyper_util::client::legacy::Error>
-where
-    C: Connect + Clone + Send + Sync + 'static,
-    B: Body + Send + 'static + Unpin,
-    B::Data: Send,
-    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-{
-    let response = client.request(request).await?;
-
-    Ok(response)
-}
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior, interval_at, timeout};
+use tracing::{Instrument as _, Level, event, span};
+
+use crate::audit::{AuditAction, AuditLog, AuditRecord};
+use crate::config::NotifierConfig;
+use crate::http_client;
+use crate::http_client::execute_request;
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
+use crate::utils::task::spawn_with_name;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 struct WebHookInvocation {
-    uri: Uri,
     container_name: Box<str>,
     container_short_id: Box<str>,
+    route: Route,
     state: State,
 }
 
 impl WebHookInvocation {
-    fn to_title(&self) -> &str {
+    fn to_title(&self) -> &'static str {
         match self.state {
             State::Success => "Container successfully restarted",
             State::Failure(_) => "Container failed to restart",
         }
     }
 
-    fn to_priority(&self) -> usize {
-        match self.state {
-            State::Success => 3,
-            State::Failure(_) => 5,
-        }
-    }
-
-    fn to_tags(&self) -> &str {
+    fn to_message(&self) -> String {
         match self.state {
-            State::Success => "white_check_mark",
-            State::Failure(_) => "x",
+            State::Success => format!(
+                "Container \"{}\" ({}) was unhealthy, but was successfully restarted.",
+                self.container_name, self.container_short_id
+            ),
+            State::Failure(ref error) => format!(
+                "Container \"{}\" ({}) was unhealthy and we failed to restarted it. Please check the logs for more info. \nError: {}",
+                self.container_name, self.container_short_id, error
+            ),
         }
     }
 }
@@ -67,8 +65,101 @@ enum State {
     Failure(eyre::Report),
 }
 
-pub struct WebHookNotifier {
+impl State {
+    fn is_success(&self) -> bool {
+        matches!(*self, State::Success)
+    }
+}
+
+/// Which webhooks a container's notifications go to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NotifyTarget {
+    /// The global webhook, and the container's own webhook, if any.
+    #[default]
+    Both,
+    /// Only the container's own webhook.
+    Container,
+}
+
+impl FromStr for NotifyTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(NotifyTarget::Both),
+            "container" => Ok(NotifyTarget::Container),
+            _ => Err(format!(
+                "Unknown notify target `{}`, expected `both` or `container`",
+                s
+            )),
+        }
+    }
+}
+
+/// Per-container notification routing, as configured through the container's labels.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Route {
     pub uri: Option<Uri>,
+    pub target: NotifyTarget,
+}
+
+fn to_priority(success: bool) -> usize {
+    if success { 3 } else { 5 }
+}
+
+fn to_tags(success: bool) -> &'static str {
+    if success { "white_check_mark" } else { "x" }
+}
+
+/// What is actually sent to the webhook.
+#[derive(Clone, Debug)]
+struct Message {
+    title: &'static str,
+    priority: usize,
+    tags: &'static str,
+    body: String,
+}
+
+impl From<&WebHookInvocation> for Message {
+    fn from(invocation: &WebHookInvocation) -> Self {
+        Message {
+            title: invocation.to_title(),
+            priority: to_priority(invocation.state.is_success()),
+            tags: to_tags(invocation.state.is_success()),
+            body: invocation.to_message(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Notification {
+    Container(WebHookInvocation),
+    /// Autoheal itself started or stopped, only goes to the global webhook.
+    Lifecycle(Message),
+    /// The configuration was reloaded.
+    Reconfigure(NotifierConfig),
+}
+
+/// What the dispatcher has yet to send, shared with the notifiers so they can tell.
+#[derive(Default)]
+struct Backlog {
+    queued: AtomicUsize,
+    in_digest: AtomicUsize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QueueDepth {
+    /// Notifications the dispatcher didn't get to yet.
+    pub queued: usize,
+    /// Containers waiting for the next digest.
+    pub in_digest: usize,
+}
+
+/// Handle to queue notifications, which are sent out by the [`NotificationDispatcher`].
+#[derive(Clone)]
+pub struct WebHookNotifier {
+    sender: UnboundedSender<Notification>,
+    backlog: Arc<Backlog>,
 }
 
 impl WebHookNotifier {
@@ -76,87 +167,979 @@ impl WebHookNotifier {
         &self,
         container_short_id: S1,
         container_name: S2,
+        route: Route,
     ) {
-        let Some(uri) = self.uri.clone() else {
-            return;
-        };
-
-        let invocation = WebHookInvocation {
-            uri,
+        self.send(Notification::Container(WebHookInvocation {
             container_name: container_name.into(),
             container_short_id: container_short_id.into(),
+            route,
             state: State::Success,
-        };
-
-        tokio::task::spawn(async move {
-            notify_webhook_and_log(invocation).await;
-        });
+        }));
     }
 
     pub fn notify_webhook_failure<S1: Into<Box<str>>, S2: Into<Box<str>>>(
         &self,
         container_name: S1,
         container_short_id: S2,
+        route: Route,
         error: eyre::Report,
     ) {
-        let Some(uri) = self.uri.clone() else {
-            return;
-        };
-
-        let invocation = WebHookInvocation {
-            uri,
+        self.send(Notification::Container(WebHookInvocation {
             container_name: container_name.into(),
             container_short_id: container_short_id.into(),
+            route,
             state: State::Failure(error),
+        }));
+    }
+
+    pub fn notify_startup(&self, details: String) {
+        self.send(Notification::Lifecycle(Message {
+            title: "Autoheal started",
+            priority: 3,
+            tags: "rocket",
+            body: details,
+        }));
+    }
+
+    pub fn notify_shutdown(&self, reason: &Shutdown) {
+        let (priority, tags) = match *reason {
+            Shutdown::Success | Shutdown::Signal(_) => (4, "stop_sign"),
+            Shutdown::OperationalFailure { .. } | Shutdown::UnexpectedError(_) => (5, "x"),
         };
 
-        tokio::task::spawn(async move {
-            notify_webhook_and_log(invocation).await;
+        self.send(Notification::Lifecycle(Message {
+            title: "Autoheal stopped",
+            priority,
+            tags,
+            body: format!("Autoheal stopped: {}", reason),
+        }));
+    }
+
+    /// Applies to the notifications queued after this one, what's in the digest is sent first when its interval changes.
+    pub fn reconfigure(&self, config: NotifierConfig) {
+        self.send(Notification::Reconfigure(config));
+    }
+
+    pub fn queue_depth(&self) -> QueueDepth {
+        QueueDepth {
+            queued: self.backlog.queued.load(Ordering::Relaxed),
+            in_digest: self.backlog.in_digest.load(Ordering::Relaxed),
+        }
+    }
+
+    fn send(&self, notification: Notification) {
+        // before sending, so the dispatcher never takes it off before we put it on
+        self.backlog.queued.fetch_add(1, Ordering::Relaxed);
+
+        if let Err(error) = self.sender.send(notification) {
+            self.backlog.queued.fetch_sub(1, Ordering::Relaxed);
+
+            event!(
+                Level::WARN,
+                notification = ?error.0,
+                "Notification dispatcher is gone, dropping notification"
+            );
+        }
+    }
+}
+
+/// When we last let a notification for a container through.
+struct LastSent {
+    at: Instant,
+    success: bool,
+    /// How many we suppressed since.
+    suppressed: usize,
+    /// Where it went, and so where the suppressed count should go.
+    destinations: Vec<Uri>,
+}
+
+/// Collapses repeated notifications for the same container within a window.
+struct Deduplicator {
+    window: Duration,
+    last_sent: HashMap<Box<str>, LastSent>,
+    /// Suppressed counts of containers whose window ran out, with their destinations, still to be mentioned.
+    expired: Vec<(Box<str>, usize, Vec<Uri>)>,
+}
+
+impl Deduplicator {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_sent: HashMap::new(),
+            expired: Vec::new(),
+        }
+    }
+
+    /// Forgets the containers whose window ran out, keeping what we suppressed for them.
+    fn prune(&mut self, now: Instant) {
+        let window = self.window;
+        let expired = &mut self.expired;
+
+        self.last_sent.retain(|container_short_id, last_sent| {
+            let keep = now.duration_since(last_sent.at) < window;
+
+            if !keep && last_sent.suppressed > 0 {
+                expired.push((
+                    container_short_id.clone(),
+                    last_sent.suppressed,
+                    std::mem::take(&mut last_sent.destinations),
+                ));
+            }
+
+            keep
         });
     }
+
+    /// Returns `None` when the notification should be suppressed, or how many were suppressed before this one.
+    fn check(
+        &mut self,
+        container_short_id: &str,
+        success: bool,
+        destinations: &[Uri],
+        now: Instant,
+    ) -> Option<usize> {
+        self.prune(now);
+
+        match self.last_sent.get_mut(container_short_id) {
+            Some(last_sent) if last_sent.success == success && !self.window.is_zero() => {
+                last_sent.suppressed += 1;
+
+                None
+            },
+            Some(_) | None => {
+                let owed = self
+                    .expired
+                    .iter()
+                    .position(|&(ref id, _, _)| &**id == container_short_id)
+                    .map_or(0, |index| self.expired.swap_remove(index).1);
+
+                let suppressed = if self.window.is_zero() {
+                    self.last_sent.remove(container_short_id)
+                } else {
+                    self.last_sent.insert(
+                        container_short_id.into(),
+                        LastSent {
+                            at: now,
+                            success,
+                            suppressed: 0,
+                            destinations: destinations.to_vec(),
+                        },
+                    )
+                }
+                .map_or(0, |last_sent| last_sent.suppressed);
+
+                Some(suppressed + owed)
+            },
+        }
+    }
+
+    /// What was suppressed for other containers whose window ran out since, with where it should be reported.
+    fn take_expired(&mut self) -> Vec<(Box<str>, usize, Vec<Uri>)> {
+        std::mem::take(&mut self.expired)
+    }
+}
+
+/// Caps how many notifications go out per minute.
+struct RateLimiter {
+    limit: usize,
+    sent: VecDeque<Instant>,
 }
 
-async fn notify_webhook_and_log(invocation: WebHookInvocation) {
-    match notify_webhook(&invocation).await {
-        Ok(()) => event!(Level::TRACE, ?invocation, "Successfully notified webhook"),
-        Err(error) => event!(Level::TRACE, ?error, ?invocation, "Failure sending webhook"),
+impl RateLimiter {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+
+        while self
+            .sent
+            .front()
+            .is_some_and(|&sent_at| now.duration_since(sent_at) >= RATE_LIMIT_PERIOD)
+        {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() < self.limit {
+            self.sent.push_back(now);
+
+            true
+        } else {
+            false
+        }
     }
 }
 
-async fn notify_webhook(invocation: &WebHookInvocation) -> Result<(), eyre::Report> {
-    let connector = HttpsConnectorBuilder::new()
-        .with_native_roots()?
-        .https_or_http()
-        .enable_all_versions()
-        .build();
+#[derive(Default)]
+struct DigestEntry {
+    container_short_id: Box<str>,
+    successes: usize,
+    failures: usize,
+}
+
+/// Per container name, in order of first appearance.
+type Digest = Vec<(Box<str>, DigestEntry)>;
+
+/// Receives notifications from the [`WebHookNotifier`]s, and applies deduplication, digests and rate limiting before sending them.
+pub struct NotificationDispatcher {
+    global_uri: Option<Uri>,
+    receiver: UnboundedReceiver<Notification>,
+    lifecycle: bool,
+    deduplicator: Deduplicator,
+    rate_limiter: RateLimiter,
+    digest_interval: Option<Duration>,
+    digests: Vec<(Uri, Digest)>,
+    /// Per destination, so that the count goes to the one that missed them.
+    dropped: HashMap<Uri, usize>,
+    /// Per destination, what the deduplicator suppressed for containers whose window ran out.
+    suppressed: HashMap<Uri, Vec<(Box<str>, usize)>>,
+    /// Per destination, the queue of its worker, so that a slow webhook only holds up its own messages.
+    deliveries: HashMap<Uri, UnboundedSender<Delivery>>,
+    workers: Vec<JoinHandle<()>>,
+    metrics: Arc<Metrics>,
+    audit: AuditLog,
+    backlog: Arc<Backlog>,
+}
 
-    let message = match invocation.state {
-        State::Success => format!(
-            "Container \"{}\" ({}) was unhealthy, but was successfully restarted.",
-            invocation.container_name, invocation.container_short_id
-        ),
-        State::Failure(ref error) => format!(
-            "Container \"{}\" ({}) was unhealthy and we failed to restarted it. Please check the logs for more info. \nError: {}",
-            invocation.container_name, invocation.container_short_id, error
-        ),
+pub fn build(
+    config: NotifierConfig,
+    metrics: Arc<Metrics>,
+    audit: AuditLog,
+) -> (WebHookNotifier, NotificationDispatcher) {
+    let (sender, receiver) = unbounded_channel();
+
+    let backlog = Arc::new(Backlog::default());
+
+    let dispatcher = NotificationDispatcher {
+        global_uri: config.webhook_url,
+        receiver,
+        lifecycle: config.lifecycle,
+        deduplicator: Deduplicator::new(config.deduplication_window),
+        rate_limiter: RateLimiter::new(config.rate_limit),
+        digest_interval: Some(config.digest_interval).filter(|interval| !interval.is_zero()),
+        digests: Vec::new(),
+        dropped: HashMap::new(),
+        suppressed: HashMap::new(),
+        deliveries: HashMap::new(),
+        workers: Vec::new(),
+        metrics,
+        audit,
+        backlog: Arc::clone(&backlog),
     };
 
+    (WebHookNotifier { sender, backlog }, dispatcher)
+}
+
+fn new_digest_timer(digest_interval: Option<Duration>) -> Option<Interval> {
+    digest_interval.map(|digest_interval| {
+        let mut digest_timer = interval_at(Instant::now() + digest_interval, digest_interval);
+        digest_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        digest_timer
+    })
+}
+
+async fn tick(digest_timer: Option<&mut Interval>) {
+    match digest_timer {
+        Some(digest_timer) => {
+            digest_timer.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+impl NotificationDispatcher {
+    /// Runs until all [`WebHookNotifier`]s are dropped, then flushes what is left, and waits for it to be delivered.
+    pub async fn run(mut self) {
+        let mut digest_timer = new_digest_timer(self.digest_interval);
+
+        loop {
+            tokio::select! {
+                notification = self.receiver.recv() => {
+                    let Some(notification) = notification else {
+                        break;
+                    };
+
+                    self.backlog.queued.fetch_sub(1, Ordering::Relaxed);
+
+                    match notification {
+                        Notification::Container(invocation) => self.handle(&invocation),
+                        Notification::Lifecycle(message) => self.handle_lifecycle(message),
+                        Notification::Reconfigure(config) => {
+                            if self.reconfigure(config) {
+                                digest_timer = new_digest_timer(self.digest_interval);
+                            }
+                        },
+                    }
+                },
+                () = tick(digest_timer.as_mut()) => {
+                    self.flush_digest();
+                },
+            }
+        }
+
+        self.flush_digest();
+
+        // the workers stop once their queue is empty and closed
+        self.deliveries.clear();
+
+        for worker in self.workers {
+            let _r = worker.await;
+        }
+    }
+
+    /// Keeps what was sent and suppressed so far, returns whether the digest interval changed.
+    fn reconfigure(&mut self, config: NotifierConfig) -> bool {
+        let digest_interval = Some(config.digest_interval).filter(|interval| !interval.is_zero());
+
+        self.global_uri = config.webhook_url;
+        self.lifecycle = config.lifecycle;
+        self.deduplicator.window = config.deduplication_window;
+        self.rate_limiter.limit = config.rate_limit;
+
+        if digest_interval == self.digest_interval {
+            return false;
+        }
+
+        // whatever was collected so far was meant to go out on the old schedule
+        self.flush_digest();
+
+        self.digest_interval = digest_interval;
+
+        true
+    }
+
+    fn destinations(&self, route: &Route) -> Vec<Uri> {
+        let global_uri = match route.target {
+            NotifyTarget::Both => self.global_uri.as_ref(),
+            NotifyTarget::Container => None,
+        };
+
+        // the same webhook only needs the message once
+        let container_uri = route.uri.as_ref().filter(|&uri| global_uri != Some(uri));
+
+        global_uri
+            .into_iter()
+            .chain(container_uri)
+            .cloned()
+            .collect()
+    }
+
+    /// Not rate limited, there are only ever a few, and the shutdown message shouldn't get lost.
+    fn handle_lifecycle(&mut self, message: Message) {
+        if !self.lifecycle {
+            return;
+        }
+
+        if let Some(global_uri) = self.global_uri.clone() {
+            self.deliver(
+                global_uri,
+                Delivery {
+                    message,
+                    containers: Vec::new(),
+                },
+            );
+        }
+    }
+
+    fn handle(&mut self, invocation: &WebHookInvocation) {
+        let destinations = self.destinations(&invocation.route);
+
+        if destinations.is_empty() {
+            return;
+        }
+
+        if self.digest_interval.is_some() {
+            for destination in destinations {
+                self.add_to_digest(destination, invocation);
+            }
+
+            return;
+        }
+
+        let suppressed = self.deduplicator.check(
+            &invocation.container_short_id,
+            invocation.state.is_success(),
+            &destinations,
+            Instant::now(),
+        );
+
+        self.owe_expired();
+
+        let Some(suppressed) = suppressed else {
+            event!(
+                Level::DEBUG,
+                ?invocation,
+                "Suppressing notification, already notified recently"
+            );
+
+            return;
+        };
+
+        let mut message = Message::from(invocation);
+
+        if suppressed > 0 {
+            let _r = write!(
+                message.body,
+                "\n{} similar notification(s) were suppressed.",
+                suppressed
+            );
+        }
+
+        for destination in destinations {
+            self.send(
+                destination,
+                message.clone(),
+                vec![(
+                    invocation.container_short_id.clone(),
+                    invocation.container_name.clone(),
+                )],
+            );
+        }
+    }
+
+    /// Moves what the deduplicator suppressed for containers whose window ran out to the destinations they were meant for.
+    fn owe_expired(&mut self) {
+        for (container_short_id, suppressed, destinations) in self.deduplicator.take_expired() {
+            for destination in destinations {
+                self.suppressed
+                    .entry(destination)
+                    .or_default()
+                    .push((container_short_id.clone(), suppressed));
+            }
+        }
+    }
+
+    fn add_to_digest(&mut self, destination: Uri, invocation: &WebHookInvocation) {
+        let digest_index = if let Some(index) = self
+            .digests
+            .iter()
+            .position(|&(ref uri, _)| *uri == destination)
+        {
+            index
+        } else {
+            self.digests.push((destination, Vec::new()));
+
+            self.digests.len() - 1
+        };
+
+        let (_, ref mut digest) = self.digests[digest_index];
+
+        let index = if let Some(index) = digest
+            .iter()
+            .position(|&(ref name, _)| *name == invocation.container_name)
+        {
+            index
+        } else {
+            digest.push((
+                invocation.container_name.clone(),
+                DigestEntry {
+                    container_short_id: invocation.container_short_id.clone(),
+                    ..DigestEntry::default()
+                },
+            ));
+
+            self.backlog.in_digest.fetch_add(1, Ordering::Relaxed);
+
+            digest.len() - 1
+        };
+
+        let (_, ref mut entry) = digest[index];
+
+        if invocation.state.is_success() {
+            entry.successes += 1;
+        } else {
+            entry.failures += 1;
+        }
+    }
+
+    fn flush_digest(&mut self) {
+        self.backlog.in_digest.store(0, Ordering::Relaxed);
+
+        for (destination, digest) in std::mem::take(&mut self.digests) {
+            self.send_digest(destination, digest);
+        }
+    }
+
+    fn send_digest(&mut self, destination: Uri, digest: Digest) {
+        let any_failures = digest.iter().any(|&(_, ref entry)| entry.failures > 0);
+
+        let mut body = format!("{} container(s) were handled:", digest.len());
+
+        for &(ref container_name, ref entry) in &digest {
+            let _r = write!(
+                body,
+                "\n- \"{}\" ({}): restarted {} time(s), failed to restart {} time(s)",
+                container_name, entry.container_short_id, entry.successes, entry.failures
+            );
+        }
+
+        let containers = digest
+            .into_iter()
+            .map(|(container_name, entry)| (entry.container_short_id, container_name))
+            .collect();
+
+        self.send(
+            destination,
+            Message {
+                title: "Autoheal digest",
+                priority: to_priority(!any_failures),
+                tags: to_tags(!any_failures),
+                body,
+            },
+            containers,
+        );
+    }
+
+    /// Applies the rate limit, and queues the message for `uri` with the containers it's about, to put on record.
+    fn send(&mut self, uri: Uri, mut message: Message, containers: Vec<(Box<str>, Box<str>)>) {
+        if !self.rate_limiter.try_acquire(Instant::now()) {
+            *self.dropped.entry(uri.clone()).or_default() += 1;
+
+            event!(
+                Level::WARN,
+                ?message,
+                "Notification rate limit reached, dropping notification"
+            );
+
+            for (container_short_id, container_name) in containers {
+                audit_notification(
+                    &self.audit,
+                    &container_short_id,
+                    &container_name,
+                    &uri,
+                    false,
+                );
+            }
+
+            return;
+        }
+
+        if let Some(dropped) = self.dropped.remove(&uri) {
+            let _r = write!(
+                message.body,
+                "\n{} notification(s) were dropped because of the rate limit.",
+                dropped
+            );
+        }
+
+        for (container_short_id, suppressed) in self.suppressed.remove(&uri).unwrap_or_default() {
+            let _r = write!(
+                message.body,
+                "\n{} notification(s) for {} were suppressed.",
+                suppressed, container_short_id
+            );
+        }
+
+        self.deliver(
+            uri,
+            Delivery {
+                message,
+                containers,
+            },
+        );
+    }
+
+    /// Hands the message to the worker of `uri`, which is started the first time.
+    fn deliver(&mut self, uri: Uri, delivery: Delivery) {
+        let sender = self.deliveries.entry(uri).or_insert_with_key(|uri| {
+            let (sender, receiver) = unbounded_channel();
+
+            self.workers.push(spawn_with_name(
+                "Webhook delivery",
+                deliver_all(
+                    uri.clone(),
+                    receiver,
+                    Arc::clone(&self.metrics),
+                    self.audit.clone(),
+                ),
+            ));
+
+            sender
+        });
+
+        // the worker only stops once we drop the sender
+        let _r = sender.send(delivery);
+    }
+}
+
+/// A message on its way to one webhook.
+struct Delivery {
+    message: Message,
+    /// The short id and name of the containers it's about, to put on record.
+    containers: Vec<(Box<str>, Box<str>)>,
+}
+
+/// Sends what's queued for `uri` in order, until the dispatcher is done.
+async fn deliver_all(
+    uri: Uri,
+    mut receiver: UnboundedReceiver<Delivery>,
+    metrics: Arc<Metrics>,
+    audit: AuditLog,
+) {
+    while let Some(delivery) = receiver.recv().await {
+        let success = deliver_one(&uri, &delivery.message).await;
+
+        metrics.webhook_delivered(success);
+
+        for (container_short_id, container_name) in delivery.containers {
+            audit_notification(&audit, &container_short_id, &container_name, &uri, success);
+        }
+    }
+}
+
+fn audit_notification(
+    audit: &AuditLog,
+    container_short_id: &str,
+    container_name: &str,
+    destination: &Uri,
+    success: bool,
+) {
+    let action = if success {
+        AuditAction::Notified
+    } else {
+        AuditAction::NotificationFailed
+    };
+
+    audit.write(&AuditRecord {
+        // only the host, the rest tends to contain tokens
+        destination: destination.host(),
+        ..AuditRecord::new(action, container_short_id, Some(container_name), None)
+    });
+}
+
+/// Returns whether the message was delivered.
+async fn deliver_one(uri: &Uri, message: &Message) -> bool {
+    let span = span!(
+        Level::INFO,
+        "notify_webhook",
+        host = uri.host(),
+        title = message.title
+    );
+
+    let success = match timeout(WEBHOOK_TIMEOUT, notify_webhook(uri, message))
+        .instrument(span)
+        .await
+    {
+        Ok(Ok(())) => {
+            event!(Level::TRACE, ?message, "Successfully notified webhook");
+
+            true
+        },
+        Ok(Err(error)) => {
+            event!(Level::TRACE, ?error, ?message, "Failure sending webhook");
+
+            false
+        },
+        Err(error) => {
+            event!(Level::TRACE, ?error, ?message, "Timeout sending webhook");
+
+            false
+        },
+    };
+
+    success
+}
+
+/// Goes straight to `uri`, without deduplication, digests or rate limiting.
+pub async fn send_test_notification(uri: &Uri) -> Result<(), eyre::Report> {
+    let message = Message {
+        title: "Autoheal test",
+        priority: 3,
+        tags: "test_tube",
+        body: "Autoheal can reach this webhook.".to_owned(),
+    };
+
+    timeout(WEBHOOK_TIMEOUT, notify_webhook(uri, &message)).await?
+}
+
+async fn notify_webhook(uri: &Uri, message: &Message) -> Result<(), eyre::Report> {
     let request = Request::builder()
-        .uri(invocation.uri.clone())
+        .uri(uri.clone())
         .method(Method::POST)
         .header(
             hyper::header::CONTENT_TYPE,
             HeaderValue::from_static("application/json"),
         )
-        .header("X-Title", invocation.to_title())
-        .header("X-Priority", invocation.to_priority())
-        .header("X-Tags", invocation.to_tags())
-        .body(Full::new(Bytes::from(message)))?;
+        .header("X-Title", message.title)
+        .header("X-Priority", message.priority)
+        .header("X-Tags", message.tags)
+        .body(Full::new(Bytes::from(message.body.clone())))?;
 
-    let client = Client::builder(TokioExecutor::new()).build(connector);
+    let client = http_client::build_client()?;
 
-    execute_request(&client, request)
-        .await
-        .map(|_| ())
-        .map_err(Into::into)
+    let response = execute_request(&client, request).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(eyre::Report::msg(format!(
+            "Webhook returned {}",
+            response.status()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use http::Uri;
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+
+    use crate::audit::AuditLog;
+    use crate::config::NotifierConfig;
+    use crate::metrics::Metrics;
+    use crate::webhook::{Deduplicator, NotifyTarget, RateLimiter, Route, build};
+
+    #[test]
+    fn deduplicate_within_window() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+
+        let start = Instant::now();
+
+        assert_eq!(deduplicator.check("abc", true, &[], start), Some(0));
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(20)),
+            None
+        );
+        // other containers are not affected
+        assert_eq!(
+            deduplicator.check("def", true, &[], start + Duration::from_secs(20)),
+            Some(0)
+        );
+        // a different outcome always goes through
+        assert_eq!(
+            deduplicator.check("abc", false, &[], start + Duration::from_secs(30)),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn deduplicate_after_window() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+
+        let start = Instant::now();
+
+        assert_eq!(deduplicator.check("abc", true, &[], start), Some(0));
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(30)),
+            None
+        );
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(60)),
+            Some(1)
+        );
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(120)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn deduplicate_reports_expired() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+
+        let start = Instant::now();
+
+        assert_eq!(deduplicator.check("abc", true, &[], start), Some(0));
+        assert_eq!(
+            deduplicator.check("abc", true, &[], start + Duration::from_secs(30)),
+            None
+        );
+        assert_eq!(
+            deduplicator.check("def", true, &[], start + Duration::from_secs(90)),
+            Some(0)
+        );
+        assert_eq!(
+            deduplicator.take_expired(),
+            vec![("abc".into(), 1, Vec::new())],
+            "Owed to `abc`, whose window ran out"
+        );
+        assert_eq!(
+            deduplicator.last_sent.len(),
+            1,
+            "Only `def` is still within its window"
+        );
+    }
+
+    #[test]
+    fn expired_go_to_their_own_route() {
+        let (_notifier, mut dispatcher) = build(
+            NotifierConfig {
+                webhook_url: None,
+                deduplication_window: Duration::from_secs(60),
+                digest_interval: Duration::ZERO,
+                rate_limit: 0,
+                lifecycle: false,
+            },
+            Arc::new(Metrics::new()),
+            AuditLog::default(),
+        );
+
+        let team_a = Uri::from_static("https://ntfy.sh/team-a");
+        let team_b = Uri::from_static("https://ntfy.sh/team-b");
+
+        let start = Instant::now();
+
+        for offset in [0, 10, 20] {
+            let _r = dispatcher.deduplicator.check(
+                "abc",
+                true,
+                std::slice::from_ref(&team_a),
+                start + Duration::from_secs(offset),
+            );
+        }
+
+        assert_eq!(
+            dispatcher.deduplicator.check(
+                "def",
+                true,
+                std::slice::from_ref(&team_b),
+                start + Duration::from_secs(90)
+            ),
+            Some(0)
+        );
+
+        dispatcher.owe_expired();
+
+        assert_eq!(
+            dispatcher.suppressed.get(&team_a),
+            Some(&vec![("abc".into(), 2)])
+        );
+        assert_eq!(
+            dispatcher.suppressed.get(&team_b),
+            None,
+            "Not told about another team's container"
+        );
+    }
+
+    #[test]
+    fn deduplicate_disabled() {
+        let mut deduplicator = Deduplicator::new(Duration::ZERO);
+
+        let start = Instant::now();
+
+        assert_eq!(deduplicator.check("abc", true, &[], start), Some(0));
+        assert_eq!(deduplicator.check("abc", true, &[], start), Some(0));
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut rate_limiter = RateLimiter::new(2);
+
+        let start = Instant::now();
+
+        assert!(rate_limiter.try_acquire(start), "First is allowed");
+        assert!(
+            rate_limiter.try_acquire(start + Duration::from_secs(1)),
+            "Second is allowed"
+        );
+        assert!(
+            !rate_limiter.try_acquire(start + Duration::from_secs(2)),
+            "Third is over the limit"
+        );
+        assert!(
+            rate_limiter.try_acquire(start + Duration::from_secs(60)),
+            "First one expired"
+        );
+        assert!(
+            !rate_limiter.try_acquire(start + Duration::from_secs(60)),
+            "Second one is still active"
+        );
+    }
+
+    #[test]
+    fn rate_limit_disabled() {
+        let mut rate_limiter = RateLimiter::new(0);
+
+        let start = Instant::now();
+
+        for _ in 0..100 {
+            assert!(rate_limiter.try_acquire(start), "Unlimited");
+        }
+    }
+
+    #[test]
+    fn destinations() {
+        let (_notifier, dispatcher) = build(
+            NotifierConfig {
+                webhook_url: Some(Uri::from_static("https://ntfy.sh/global")),
+                deduplication_window: Duration::ZERO,
+                digest_interval: Duration::ZERO,
+                rate_limit: 0,
+                lifecycle: false,
+            },
+            Arc::new(Metrics::new()),
+            AuditLog::default(),
+        );
+
+        let team = Uri::from_static("https://ntfy.sh/team");
+
+        assert_eq!(
+            dispatcher.destinations(&Route {
+                uri: Some(team.clone()),
+                target: NotifyTarget::Both,
+            }),
+            vec![Uri::from_static("https://ntfy.sh/global"), team.clone()]
+        );
+        assert_eq!(
+            dispatcher.destinations(&Route {
+                uri: Some(team.clone()),
+                target: NotifyTarget::Container,
+            }),
+            vec![team]
+        );
+        assert_eq!(
+            dispatcher.destinations(&Route {
+                uri: Some(Uri::from_static("https://ntfy.sh/global")),
+                target: NotifyTarget::Both,
+            }),
+            vec![Uri::from_static("https://ntfy.sh/global")],
+            "Sent once when the container's webhook is the global one"
+        );
+        assert_eq!(
+            dispatcher.destinations(&Route::default()),
+            vec![Uri::from_static("https://ntfy.sh/global")]
+        );
+    }
+
+    #[test]
+    fn queue_depth() {
+        let (notifier, dispatcher) = build(
+            NotifierConfig {
+                webhook_url: None,
+                deduplication_window: Duration::ZERO,
+                digest_interval: Duration::ZERO,
+                rate_limit: 0,
+                lifecycle: false,
+            },
+            Arc::new(Metrics::new()),
+            AuditLog::default(),
+        );
+
+        notifier.notify_startup("first".to_owned());
+        notifier.notify_startup("second".to_owned());
+
+        assert_eq!(notifier.queue_depth().queued, 2);
+
+        drop(dispatcher);
+
+        // dropped, so it's not queued either
+        notifier.notify_startup("third".to_owned());
+
+        assert_eq!(notifier.queue_depth().queued, 2);
+    }
 }